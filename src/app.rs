@@ -1,20 +1,106 @@
-use tui::widgets::ListState;
+use serde::Deserialize;
+use tui::layout::Rect;
 
-use crate::mpd::{Status, Track};
+use std::{
+    cmp::{max, min},
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Gauge, SearchFolding},
+    input::{Edit, LineInput},
+    keys::HelpEntry,
+    mpd::{PlayerState, Queue, Status},
+    search::{self, Field, Matched, TrackString},
+};
 
 pub struct State {
     pub status: Status,
-    pub queue: Vec<Track>,
-    pub selected: usize,
-    pub liststate: ListState,
+    // when the status was fetched, to count the elapsed time from
+    pub status_time: Instant,
+    pub queue: Queue,
+    // queue positions drawn before their songs were loaded
+    pub missing: Vec<usize>,
     pub searching: bool,
+    pub query: LineInput,
+    // whether the query highlights matches in the whole queue instead of filtering it
+    pub finding: bool,
+    pub filtered: Vec<usize>,
+    pub matched: Vec<Matched>,
+    pub query_error: Option<String>,
+    pub pending: String,
+    pub commanding: bool,
+    pub cmdline: String,
+    pub output: Vec<String>,
+    pub output_offset: usize,
+    pub marked: HashSet<usize>,
+    pub visual: Option<usize>,
+    pub clickables: Vec<(Rect, Clickable)>,
+    pub screen: usize,
+    pub screen_states: Vec<ScreenState>,
+    pub help: Option<Help>,
+    pub help_entries: Vec<HelpEntry>,
+    pub modal: Option<Modal>,
+    pub messages: Vec<Message>,
+    pub message_deadline: Option<Instant>,
+}
+
+pub struct Message {
+    pub text: String,
+    pub error: bool,
+}
+
+#[derive(Default)]
+pub struct Help {
     pub query: String,
+    pub searching: bool,
     pub filtered: Vec<usize>,
+    pub offset: usize,
+}
+
+// dialogs drawn above everything else that take all input while open
+pub enum Modal {
+    Confirm {
+        prompt: String,
+        cmd: Command,
+    },
+    Input {
+        prompt: String,
+        input: String,
+        cmd: fn(String) -> Command,
+    },
+    Pick {
+        prompt: String,
+        items: Vec<String>,
+        selected: usize,
+        cmd: fn(String) -> Command,
+    },
+}
+
+pub struct ScreenState {
+    pub panes: Vec<Pane>,
+    pub focus: usize,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Default)]
+pub struct Pane {
+    pub selected: usize,
+    pub offset: usize,
+}
+
+#[derive(Clone)]
+pub enum Clickable {
+    Row(usize, usize),
+    Screen(usize),
+    Button(Command),
+    Gauge(Gauge),
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub enum Command {
     Quit,
+    Suspend,
     ToggleRepeat,
     ToggleRandom,
     ToggleSingle,
@@ -24,9 +110,13 @@ pub enum Command {
     Stop,
     SeekBackwards,
     SeekForwards,
+    SeekTo(f32),
+    SetVolume(u8),
     Previous,
     Next,
     Play,
+    Delete,
+    Select(usize),
     Reselect,
     Down,
     Up,
@@ -36,46 +126,714 @@ pub enum Command {
     GotoBottom,
     InputSearch(char),
     BackspaceSearch,
+    EditSearch(Edit),
     ClearSearch,
     QuitSearch,
     Searching(bool),
+    Find,
+    NextMatch,
+    PreviousMatch,
+    InputCmdline(char),
+    BackspaceCmdline,
+    ClearCmdline,
+    CompleteCmdline,
+    PreviousHistory,
+    NextHistory,
+    RunCmdline,
+    QuitCmdline,
+    Commanding(bool),
+    ScrollOutputUp,
+    ScrollOutputDown,
+    ClearOutput,
+    ToggleMark,
+    ToggleVisual,
+    QuitVisual,
+    MarkAll,
+    ClearMarks,
+    MoveUp,
+    MoveDown,
+    Priority,
+    AddToPlaylist(String),
+    Clear,
+    ForceClear,
+    Save(String),
+    ForceSave(String),
+    NextScreen,
+    PreviousScreen,
+    FocusNext,
+    FocusPrevious,
+    Help,
+    QuitHelp,
+    ConfirmModal,
+    QuitModal,
+    InputModal(char),
+    BackspaceModal,
+    ClearModal,
+    Paste(String),
+}
+
+impl Command {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Command::Quit => "quit mmtc",
+            Command::Suspend => "suspend mmtc",
+            Command::ToggleRepeat => "toggle repeat",
+            Command::ToggleRandom => "toggle random",
+            Command::ToggleSingle => "toggle single",
+            Command::ToggleOneshot => "toggle oneshot",
+            Command::ToggleConsume => "toggle consume",
+            Command::TogglePause => "toggle pause",
+            Command::Stop => "stop",
+            Command::SeekBackwards => "seek backwards",
+            Command::SeekForwards => "seek forwards",
+            Command::SeekTo(_) => "seek to a part of the current song",
+            Command::SetVolume(_) => "set the volume",
+            Command::Previous => "previous song",
+            Command::Next => "next song",
+            Command::Play => "play selected song or the marked songs",
+            Command::Delete => "delete selected song or the marked songs",
+            Command::Select(_) => "select a song",
+            Command::Reselect => "select current song",
+            Command::Down => "go down",
+            Command::Up => "go up",
+            Command::JumpDown => "jump down",
+            Command::JumpUp => "jump up",
+            Command::GotoTop => "go to the top",
+            Command::GotoBottom => "go to the bottom",
+            Command::InputSearch(_) => "type into the query",
+            Command::BackspaceSearch => "delete the character before the cursor in the query",
+            Command::EditSearch(edit) => edit.description(),
+            Command::ClearSearch => "empty the query",
+            Command::QuitSearch => "quit searching mode and empty the query",
+            Command::Searching(true) => "enter searching mode",
+            Command::Searching(false) => "quit searching mode and keep the query",
+            Command::Find => "enter searching mode without filtering the queue",
+            Command::NextMatch => "go to the next song matching the query",
+            Command::PreviousMatch => "go to the previous song matching the query",
+            Command::InputCmdline(_) => "type into the command line",
+            Command::BackspaceCmdline => "delete the last character of the command line",
+            Command::ClearCmdline => "empty the command line",
+            Command::CompleteCmdline => "complete the command line",
+            Command::PreviousHistory => "previous entry in history",
+            Command::NextHistory => "next entry in history",
+            Command::RunCmdline => "run the command line",
+            Command::QuitCmdline => "quit command mode",
+            Command::Commanding(true) => "enter command mode",
+            Command::Commanding(false) => "quit command mode",
+            Command::ScrollOutputUp => "scroll the command output up",
+            Command::ScrollOutputDown => "scroll the command output down",
+            Command::ClearOutput => "clear the command output",
+            Command::ToggleMark => "mark or unmark selected song",
+            Command::ToggleVisual => "enter visual mode or mark the visual range",
+            Command::QuitVisual => "quit visual mode",
+            Command::MarkAll => "mark all songs",
+            Command::ClearMarks => "unmark all songs",
+            Command::MoveUp => "move selected song or the marked songs up",
+            Command::MoveDown => "move selected song or the marked songs down",
+            Command::Priority => "set the priority of selected song or the marked songs",
+            Command::AddToPlaylist(_) => "add selected song or the marked songs to a playlist",
+            Command::Clear => "clear the queue after confirmation",
+            Command::ForceClear => "clear the queue",
+            Command::Save(_) => "save the queue as a playlist after confirming overwrites",
+            Command::ForceSave(_) => "save the queue as a playlist",
+            Command::NextScreen => "go to the next screen",
+            Command::PreviousScreen => "go to the previous screen",
+            Command::FocusNext => "focus the next queue",
+            Command::FocusPrevious => "focus the previous queue",
+            Command::Help => "show this help",
+            Command::QuitHelp => "close this help",
+            Command::ConfirmModal => "confirm the dialog",
+            Command::QuitModal => "close the dialog",
+            Command::InputModal(_) => "type into the dialog",
+            Command::BackspaceModal => "delete the last character of the dialog input",
+            Command::ClearModal => "empty the dialog input",
+            Command::Paste(_) => "paste into the query",
+        }
+    }
+}
+
+impl Pane {
+    fn select(&mut self, x: usize) {
+        self.selected = x;
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+    }
 }
 
 impl State {
+    pub fn pane(&self) -> &Pane {
+        let screen = &self.screen_states[self.screen];
+        &screen.panes[screen.focus]
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        let screen = &mut self.screen_states[self.screen];
+        &mut screen.panes[screen.focus]
+    }
+
+    fn panes_mut(&mut self) -> impl Iterator<Item = &mut Pane> {
+        self.screen_states
+            .iter_mut()
+            .flat_map(|screen| &mut screen.panes)
+    }
+
+    pub fn selected(&self) -> usize {
+        self.pane().selected
+    }
+
+    pub fn focus(&self) -> usize {
+        self.screen_states[self.screen].focus
+    }
+
+    pub fn set_focus(&mut self, focus: usize) {
+        let screen = &mut self.screen_states[self.screen];
+        if focus < screen.panes.len() && focus != screen.focus {
+            screen.focus = focus;
+            self.visual = None;
+            if self.selected() >= self.len() {
+                self.reselect();
+            }
+        }
+    }
+
     pub fn select(&mut self, x: usize) {
-        self.selected = x;
-        self.liststate.select(Some(x));
+        self.pane_mut().select(x);
+    }
+
+    // select the current song in every pane
+    pub fn reselect_all(&mut self) {
+        let x = self.status.song.as_ref().map_or(0, |song| song.pos);
+        for pane in self.panes_mut() {
+            pane.select(x);
+        }
+    }
+
+    // keep the selections in range after the queue changed
+    pub fn reload_panes(&mut self) {
+        let len = self.queue.len();
+        let x = self.status.song.as_ref().map_or(0, |song| song.pos);
+        for pane in self.panes_mut() {
+            pane.reset();
+            pane.select(if pane.selected < len {
+                pane.selected
+            } else {
+                x
+            });
+        }
     }
 
     pub fn reselect(&mut self) {
         self.select(self.status.song.as_ref().map_or(0, |song| song.pos));
     }
 
+    pub fn down(&mut self, n: usize, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if cycle {
+            self.select(selected.saturating_add(n) % len);
+        } else {
+            self.select(min(selected.saturating_add(n), len - 1));
+        }
+    }
+
+    pub fn up(&mut self, n: usize, cycle: bool) {
+        let len = self.len();
+        let selected = self.selected();
+        if selected >= len {
+            self.reselect();
+        } else if cycle {
+            self.select((selected + len - n % len) % len);
+        } else {
+            self.select(selected.saturating_sub(n));
+        }
+    }
+
+    pub fn switch_screen(&mut self, screen: usize) {
+        if screen == self.screen || screen >= self.screen_states.len() {
+            return;
+        }
+        self.screen = screen;
+        self.visual = None;
+        if self.selected() >= self.len() {
+            self.reselect();
+        }
+    }
+
+    pub fn clicked(&self, x: u16, y: u16) -> Option<(Rect, Clickable)> {
+        self.clickables
+            .iter()
+            .rev()
+            .find(|(area, _)| {
+                area.x <= x && x < area.x + area.width && area.y <= y && y < area.y + area.height
+            })
+            .cloned()
+    }
+
+    // the elapsed time of the current song, counted locally since the last status while playing
+    pub fn elapsed(&self) -> Option<f32> {
+        self.status.song.as_ref().map(|song| {
+            if self.status.state == PlayerState::Play {
+                song.elapsed + self.status_time.elapsed().as_secs_f32()
+            } else {
+                song.elapsed
+            }
+        })
+    }
+
+    pub fn filtering(&self) -> bool {
+        !self.query.is_empty() && !self.finding
+    }
+
     pub fn len(&self) -> usize {
-        if self.query.is_empty() {
+        if self.filtering() {
+            self.filtered.len()
+        } else {
             self.queue.len()
+        }
+    }
+
+    pub fn queue_pos(&self, i: usize) -> Option<usize> {
+        if self.filtering() {
+            self.filtered.get(i).copied()
         } else {
-            self.filtered.len()
+            (i < self.queue.len()).then_some(i)
+        }
+    }
+
+    // the matched characters of a song in the whole queue when finding
+    pub fn found(&self, x: usize) -> &[(Field, usize)] {
+        match self.filtered.binary_search(&x) {
+            Ok(i) if self.finding && !self.query.is_empty() => &self.matched[i],
+            _ => &[],
+        }
+    }
+
+    // the index of the selected song among the matches starting from 1, or 0 if it doesn't match
+    pub fn match_index(&self) -> usize {
+        let selected = self.selected();
+        if self.finding {
+            self.filtered.binary_search(&selected).map_or(0, |i| i + 1)
+        } else if selected < self.filtered.len() {
+            selected + 1
+        } else {
+            0
+        }
+    }
+
+    // select the nth match after the selected song, wrapping around,
+    // or the first match starting from the selected song if n is 0
+    pub fn next_match(&mut self, n: usize) {
+        let len = self.filtered.len();
+        if len == 0 {
+            return;
+        }
+        let selected = self.selected();
+        let i = if n == 0 {
+            self.filtered.partition_point(|&x| x < selected)
+        } else {
+            self.filtered.partition_point(|&x| x <= selected) + n - 1
+        };
+        self.select(self.filtered[i % len]);
+    }
+
+    // select the nth match before the selected song, wrapping around
+    pub fn previous_match(&mut self, n: usize) {
+        let len = self.filtered.len();
+        if len == 0 {
+            return;
         }
+        let i = self.filtered.partition_point(|&x| x < self.selected());
+        self.select(self.filtered[(i + len - n % len) % len]);
     }
 
-    pub fn update_search(&mut self, queue_strings: &[String]) {
-        let query = self.query.to_lowercase();
-        self.filtered.clear();
-        for (i, track) in queue_strings.iter().enumerate() {
-            if track.contains(&query) {
-                self.filtered.push(i);
+    // switch between filtering and finding, keeping the selected song when finding
+    pub fn set_finding(
+        &mut self,
+        finding: bool,
+        queue_strings: &[TrackString],
+        fuzzy: bool,
+        folding: &SearchFolding,
+    ) {
+        if finding == self.finding {
+            return;
+        }
+        let x = self.queue_pos(self.selected());
+        self.finding = finding;
+        if !self.query.is_empty() {
+            self.update_search(queue_strings, fuzzy, folding);
+            if finding {
+                match x {
+                    Some(x) => self.select(x),
+                    None => self.reselect(),
+                }
+            }
+        }
+    }
+
+    pub fn has_marks(&self) -> bool {
+        self.visual.is_some() || !self.marked.is_empty()
+    }
+
+    pub fn is_marked(&self, i: usize, id: usize) -> bool {
+        let selected = self.selected();
+        self.marked.contains(&id)
+            || matches!(
+                self.visual,
+                Some(anchor) if min(anchor, selected) <= i && i <= max(anchor, selected),
+            )
+    }
+
+    // queue positions of the marked songs, or the selected song if nothing is marked
+    pub fn targets(&self) -> Vec<usize> {
+        let mut xs = Vec::new();
+        let selected = self.selected();
+        if let Some(anchor) = self.visual {
+            xs.extend(
+                (min(anchor, selected) ..= max(anchor, selected)).filter_map(|i| self.queue_pos(i)),
+            );
+        }
+        if !self.marked.is_empty() {
+            xs.extend(
+                self.queue
+                    .iter()
+                    .filter_map(|(x, track)| self.marked.contains(&track.id).then_some(x)),
+            );
+        }
+        xs.sort_unstable();
+        xs.dedup();
+
+        if xs.is_empty() {
+            xs.extend(self.queue_pos(selected));
+        }
+        xs
+    }
+
+    pub fn toggle_marks(&mut self, n: usize) {
+        let len = self.len();
+        let selected = self.selected();
+        for i in selected .. min(selected.saturating_add(n), len) {
+            if let Some(track) = self.queue_pos(i).and_then(|x| self.queue.get(x)) {
+                if !self.marked.remove(&track.id) {
+                    self.marked.insert(track.id);
+                }
+            }
+        }
+        self.down(n, false);
+    }
+
+    // the songs to mark all of, only the matches when finding since every song is listed then
+    pub fn markable(&self) -> Vec<usize> {
+        if self.finding {
+            self.filtered.clone()
+        } else {
+            (0 .. self.len())
+                .filter_map(|i| self.queue_pos(i))
+                .collect()
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        for x in self.markable() {
+            if let Some(track) = self.queue.get(x) {
+                self.marked.insert(track.id);
+            }
+        }
+    }
+
+    pub fn commit_visual(&mut self) {
+        for x in self.targets() {
+            if let Some(track) = self.queue.get(x) {
+                self.marked.insert(track.id);
+            }
+        }
+        self.visual = None;
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual = None;
+    }
+
+    // keep the previous results if the query can't be parsed
+    pub fn update_search(
+        &mut self,
+        queue_strings: &[TrackString],
+        fuzzy: bool,
+        folding: &SearchFolding,
+    ) {
+        let query = match search::parse(self.query.as_str(), folding) {
+            Ok(query) => query,
+            Err(e) => {
+                self.query_error = Some(e);
+                return;
             }
+        };
+        self.query_error = None;
+        let mut results = search::search(queue_strings, &query, fuzzy);
+        if self.finding {
+            // matches are visited in queue order
+            results.sort_unstable_by_key(|(i, _)| *i);
+            (self.filtered, self.matched) = results.into_iter().unzip();
+            return;
+        }
+        (self.filtered, self.matched) = results.into_iter().unzip();
+        for pane in self.panes_mut() {
+            pane.reset();
+            pane.select(0);
         }
-        self.liststate.select(None);
-        self.select(0);
+        self.visual = None;
+    }
+
+    // search again after editing the query
+    pub fn edit_query(
+        &mut self,
+        edit: impl FnOnce(&mut LineInput),
+        queue_strings: &[TrackString],
+        fuzzy: bool,
+        folding: &SearchFolding,
+    ) {
+        let filtering = self.filtering();
+        let empty = self.query.is_empty();
+        edit(&mut self.query);
+        if self.query.is_empty() {
+            if filtering {
+                self.reselect();
+            }
+            return;
+        }
+
+        if empty {
+            if self.finding {
+                self.filtered.clear();
+                self.matched.clear();
+            } else {
+                // start from the whole queue in case the query can't be parsed
+                self.filtered = (0 .. self.queue.len()).collect();
+                self.matched = vec![Vec::new(); self.queue.len()];
+            }
+        }
+        self.update_search(queue_strings, fuzzy, folding);
+        if self.finding {
+            self.next_match(0);
+        }
+    }
+
+    pub fn notify(&mut self, text: String, error: bool, timeout: Duration) {
+        self.messages.push(Message { text, error });
+        self.message_deadline = Some(Instant::now() + timeout);
+    }
+
+    pub fn query_error(&self) -> Option<&str> {
+        self.query_error
+            .as_deref()
+            .filter(|_| !self.query.is_empty())
+    }
+
+    // the last message if it has not expired
+    pub fn message(&self) -> Option<&Message> {
+        self.message_deadline.and(self.messages.last())
+    }
+
+    pub fn expire_message(&mut self) -> bool {
+        if matches!(self.message_deadline, Some(deadline) if deadline <= Instant::now()) {
+            self.message_deadline = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn update_help(&mut self) {
+        if let Some(help) = &mut self.help {
+            let query = help.query.to_lowercase();
+            help.filtered.clear();
+            for (i, entry) in self.help_entries.iter().enumerate() {
+                if [entry.mode, &entry.keys, &entry.command, entry.description]
+                    .iter()
+                    .any(|x| x.to_lowercase().contains(&query))
+                {
+                    help.filtered.push(i);
+                }
+            }
+            help.offset = 0;
+        }
+    }
+
+    // run a navigation or search command in the help popup
+    pub fn help_command(&mut self, cmd: Command, count: Option<usize>, jump_lines: usize) {
+        if let Some(help) = &mut self.help {
+            let n = count.unwrap_or(1);
+            match cmd {
+                Command::Down | Command::NextHistory => {
+                    help.offset = help.offset.saturating_add(n);
+                }
+                Command::Up | Command::PreviousHistory => {
+                    help.offset = help.offset.saturating_sub(n);
+                }
+                Command::JumpDown => {
+                    help.offset = help.offset.saturating_add(jump_lines.saturating_mul(n));
+                }
+                Command::JumpUp => {
+                    help.offset = help.offset.saturating_sub(jump_lines.saturating_mul(n));
+                }
+                Command::GotoTop => help.offset = count.map_or(0, |n| n.saturating_sub(1)),
+                Command::GotoBottom => {
+                    help.offset = count.map_or(usize::MAX, |n| n.saturating_sub(1));
+                }
+                Command::InputSearch(c) => help.query.push(c),
+                Command::BackspaceSearch => {
+                    help.query.pop();
+                }
+                Command::ClearSearch => help.query.clear(),
+                Command::Paste(ref x) if help.searching => help.query.push_str(x),
+                Command::QuitSearch => {
+                    help.searching = false;
+                    help.query.clear();
+                }
+                Command::Searching(x) => help.searching = x,
+                _ => return,
+            }
+            if matches!(
+                cmd,
+                Command::InputSearch(_)
+                    | Command::BackspaceSearch
+                    | Command::ClearSearch
+                    | Command::QuitSearch
+                    | Command::Paste(_),
+            ) {
+                self.update_help();
+            }
+        }
+    }
+
+    // returns the command to run when the dialog is confirmed
+    pub fn modal_command(&mut self, cmd: Command, count: Option<usize>) -> Option<Command> {
+        let n = count.unwrap_or(1);
+        match (cmd, self.modal.as_mut()?) {
+            (Command::ConfirmModal, _) => match self.modal.take()? {
+                Modal::Confirm { cmd, .. } => return Some(cmd),
+                Modal::Input { input, cmd, .. } if !input.is_empty() => return Some(cmd(input)),
+                Modal::Pick {
+                    mut items,
+                    selected,
+                    cmd,
+                    ..
+                } => return Some(cmd(items.swap_remove(selected))),
+                modal => self.modal = Some(modal),
+            },
+            (Command::QuitModal, _) => self.modal = None,
+            (Command::InputModal(c), Modal::Input { input, .. }) => input.push(c),
+            (Command::BackspaceModal, Modal::Input { input, .. }) => {
+                input.pop();
+            }
+            (Command::ClearModal, Modal::Input { input, .. }) => input.clear(),
+            (
+                Command::Down,
+                Modal::Pick {
+                    items, selected, ..
+                },
+            ) => {
+                *selected = min(selected.saturating_add(n), items.len() - 1);
+            }
+            (Command::Up, Modal::Pick { selected, .. }) => {
+                *selected = selected.saturating_sub(n);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    pub fn quit_command(&mut self) {
+        self.commanding = false;
+        self.cmdline.clear();
     }
 
     pub fn quit_search(&mut self) {
         self.searching = false;
-        if !self.query.is_empty() {
-            self.query.clear();
+        if self.filtering() {
+            self.visual = None;
             self.reselect();
         }
+        self.query.clear();
+        self.finding = false;
+    }
+}
+
+// the moves as ids and destinations that put the songs at the given positions right after the
+// current song in order, following where every move shifts them and the current song to
+pub fn moves_after(xs: &[(usize, usize)], cur: Option<usize>) -> Vec<(usize, usize)> {
+    let mut pos: Vec<_> = xs.iter().map(|&(x, _)| x).collect();
+    let mut cur_pos = cur;
+    let mut to = None;
+    let mut moves = Vec::with_capacity(xs.len());
+    for (i, &(x, id)) in xs.iter().enumerate() {
+        if Some(x) == cur {
+            continue;
+        }
+        // the destination is counted after the song is taken out
+        let from = pos[i];
+        let dest = match to {
+            Some(to) if from < to => to - 1,
+            Some(to) => to,
+            None => cur_pos.map_or(0, |cur| if from < cur { cur } else { cur + 1 }),
+        };
+        for y in pos.iter_mut().chain(cur_pos.iter_mut()) {
+            if from < *y && *y <= dest {
+                *y -= 1;
+            } else if dest <= *y && *y < from {
+                *y += 1;
+            }
+        }
+        pos[i] = dest;
+        moves.push((id, dest));
+        to = Some(dest + 1);
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // play the marked songs of `A B C D E` with C playing
+    fn play(marked: &[usize], cur: Option<usize>) -> String {
+        let mut queue: Vec<_> = "ABCDE".chars().collect();
+        let xs: Vec<_> = marked.iter().map(|&x| (x, x)).collect();
+        for (id, dest) in moves_after(&xs, cur) {
+            let from = queue
+                .iter()
+                .position(|&c| c as usize - 'A' as usize == id)
+                .unwrap();
+            let c = queue.remove(from);
+            queue.insert(dest, c);
+        }
+        queue.into_iter().collect()
+    }
+
+    #[test]
+    fn moves_before_current() {
+        assert_eq!(play(&[0, 1], Some(2)), "CABDE");
+    }
+
+    #[test]
+    fn moves_after_current() {
+        assert_eq!(play(&[3, 4], Some(2)), "ABCDE");
+        assert_eq!(play(&[4], Some(2)), "ABCED");
+    }
+
+    #[test]
+    fn moves_around_current() {
+        assert_eq!(play(&[0, 4], Some(2)), "BCAED");
+        assert_eq!(play(&[4, 0, 1], Some(2)), "CEABD");
+        assert_eq!(play(&[1, 2, 4], Some(2)), "ACBED");
+    }
+
+    #[test]
+    fn moves_without_current() {
+        assert_eq!(play(&[3, 1], None), "DBACE");
     }
 }
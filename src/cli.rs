@@ -22,6 +22,14 @@ pub struct Opts {
     #[arg(long)]
     pub cycle: bool,
 
+    /// Rank search results by fuzzy matching
+    #[arg(long)]
+    pub fuzzy_search: bool,
+
+    /// Load the queue in windows around the view and search it on the server
+    #[arg(long)]
+    pub lazy_queue: bool,
+
     /// Don't clear query on play
     #[arg(long, overrides_with = "clear_query_on_play")]
     pub no_clear_query_on_play: bool,
@@ -30,6 +38,14 @@ pub struct Opts {
     #[arg(long, overrides_with = "cycle")]
     pub no_cycle: bool,
 
+    /// Don't rank search results by fuzzy matching
+    #[arg(long, overrides_with = "fuzzy_search")]
+    pub no_fuzzy_search: bool,
+
+    /// Load the whole queue at once and search it locally
+    #[arg(long, overrides_with = "lazy_queue")]
+    pub no_lazy_queue: bool,
+
     /// Specify the address of the mpd server
     #[arg(long, value_name = "address")]
     pub address: Option<String>,
@@ -38,15 +54,27 @@ pub struct Opts {
     #[arg(short, long, value_name = "file")]
     pub config: Option<PathBuf>,
 
+    /// The number of entries to keep in the search and command histories
+    #[arg(long, value_name = "number")]
+    pub history_size: Option<usize>,
+
     /// The number of lines to jump
     #[arg(long, value_name = "number")]
     pub jump_lines: Option<usize>,
 
+    /// The time to wait for the next key of a key sequence in seconds
+    #[arg(long, value_name = "number")]
+    pub key_timeout: Option<f32>,
+
+    /// The time to show a message for in seconds
+    #[arg(long, value_name = "number")]
+    pub message_timeout: Option<f32>,
+
     /// The time to seek in seconds
     #[arg(long, value_name = "number")]
     pub seek_secs: Option<f32>,
 
-    /// The amount of status updates per second
+    /// The amount of frame updates per second while playing
     #[arg(long, value_name = "number")]
     pub ups: Option<f32>,
 }
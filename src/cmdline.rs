@@ -0,0 +1,232 @@
+use anyhow::Result;
+
+use crate::{
+    app::Command,
+    mpd::{quote, Client},
+};
+
+pub const ACTIONS: &[(&str, Command)] = &[
+    ("Quit", Command::Quit),
+    ("Suspend", Command::Suspend),
+    ("ToggleRepeat", Command::ToggleRepeat),
+    ("ToggleRandom", Command::ToggleRandom),
+    ("ToggleSingle", Command::ToggleSingle),
+    ("ToggleOneshot", Command::ToggleOneshot),
+    ("ToggleConsume", Command::ToggleConsume),
+    ("TogglePause", Command::TogglePause),
+    ("Stop", Command::Stop),
+    ("SeekBackwards", Command::SeekBackwards),
+    ("SeekForwards", Command::SeekForwards),
+    ("Previous", Command::Previous),
+    ("Next", Command::Next),
+    ("Play", Command::Play),
+    ("Delete", Command::Delete),
+    ("Reselect", Command::Reselect),
+    ("Down", Command::Down),
+    ("Up", Command::Up),
+    ("JumpDown", Command::JumpDown),
+    ("JumpUp", Command::JumpUp),
+    ("GotoTop", Command::GotoTop),
+    ("GotoBottom", Command::GotoBottom),
+    ("ClearSearch", Command::ClearSearch),
+    ("QuitSearch", Command::QuitSearch),
+    ("NextMatch", Command::NextMatch),
+    ("PreviousMatch", Command::PreviousMatch),
+    ("ScrollOutputUp", Command::ScrollOutputUp),
+    ("ScrollOutputDown", Command::ScrollOutputDown),
+    ("ClearOutput", Command::ClearOutput),
+    ("ToggleMark", Command::ToggleMark),
+    ("ToggleVisual", Command::ToggleVisual),
+    ("QuitVisual", Command::QuitVisual),
+    ("MarkAll", Command::MarkAll),
+    ("ClearMarks", Command::ClearMarks),
+    ("MoveUp", Command::MoveUp),
+    ("MoveDown", Command::MoveDown),
+    ("Priority", Command::Priority),
+    ("AddToPlaylist", Command::AddToPlaylist(String::new())),
+    ("Clear", Command::Clear),
+    ("ForceClear", Command::ForceClear),
+    ("Save", Command::Save(String::new())),
+    ("ForceSave", Command::ForceSave(String::new())),
+    ("NextScreen", Command::NextScreen),
+    ("PreviousScreen", Command::PreviousScreen),
+    ("FocusNext", Command::FocusNext),
+    ("FocusPrevious", Command::FocusPrevious),
+];
+
+#[derive(Default)]
+pub struct Completer {
+    commands: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+}
+
+// parse a command line as an mmtc action with an optional count, e.g. `GotoBottom 20`
+pub fn parse_action(line: &str) -> Option<(Command, Option<usize>)> {
+    let (name, arg) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, arg)| (name, arg.trim()));
+    let (_, cmd) = ACTIONS.iter().find(|(x, _)| *x == name)?;
+
+    Some(match cmd {
+        Command::AddToPlaylist(_) => (Command::AddToPlaylist(arg.into()), None),
+        Command::Save(_) => (Command::Save(arg.into()), None),
+        Command::ForceSave(_) if !arg.is_empty() => (Command::ForceSave(arg.into()), None),
+        Command::ForceSave(_) => return None,
+        _ if arg.is_empty() => (cmd.clone(), None),
+        _ => (cmd.clone(), Some(arg.parse().ok()?)),
+    })
+}
+
+// returns the byte offset and the index of the last token
+fn last_token(line: &str) -> (usize, usize) {
+    let mut start = 0;
+    let mut idx = 0;
+    let mut in_token = false;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && quoted {
+            escaped = true;
+        } else if c.is_whitespace() && !quoted {
+            if in_token {
+                idx += 1;
+                in_token = false;
+            }
+            start = i + c.len_utf8();
+        } else {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            if !in_token {
+                in_token = true;
+                start = i;
+            }
+        }
+    }
+
+    (start, idx)
+}
+
+fn unquote(token: &str) -> String {
+    let mut unquoted = String::with_capacity(token.len());
+    let mut escaped = false;
+    for c in token.chars() {
+        if escaped {
+            escaped = false;
+            unquoted.push(c);
+        } else if c == '\\' {
+            escaped = true;
+        } else if c != '"' {
+            unquoted.push(c);
+        }
+    }
+    unquoted
+}
+
+fn values(lines: Vec<String>, key: &str) -> Vec<String> {
+    lines
+        .into_iter()
+        .filter_map(|line| line.strip_prefix(key).map(String::from))
+        .collect()
+}
+
+impl Completer {
+    // complete the last token of the command line in place,
+    // returning the candidates if there are more than one of them
+    pub async fn complete(&mut self, cl: &mut Client, line: &mut String) -> Result<Vec<String>> {
+        let (start, idx) = last_token(line);
+        let quoted = line[start ..].starts_with('"');
+        let token = unquote(&line[start ..]);
+
+        // (candidate, whether the candidate is a complete token)
+        let mut candidates = Vec::new();
+        if idx == 0 {
+            if self.commands.is_none() {
+                self.commands = Some(values(cl.command_lines(b"commands").await?, "command: "));
+            }
+            for (name, _) in ACTIONS {
+                if name.starts_with(&token) {
+                    candidates.push((String::from(*name), true));
+                }
+            }
+            for name in self.commands.iter().flatten() {
+                if name.starts_with(&token) {
+                    candidates.push((name.clone(), true));
+                }
+            }
+        } else {
+            if self.tags.is_none() {
+                self.tags = Some(values(cl.command_lines(b"tagtypes").await?, "tagtype: "));
+            }
+            let lower = token.to_lowercase();
+            for tag in self.tags.iter().flatten() {
+                if tag.to_lowercase().starts_with(&lower) {
+                    candidates.push((tag.clone(), true));
+                }
+            }
+
+            let dir = token.rfind('/').map_or("", |i| &token[.. i]);
+            let lines = cl
+                .command_lines(format!("lsinfo {}", quote(dir)).as_bytes())
+                .await?;
+            for line in lines {
+                if let Some(uri) = line.strip_prefix("directory: ") {
+                    if uri.starts_with(&token) {
+                        candidates.push((format!("{uri}/"), false));
+                    }
+                } else if let Some(uri) = line.strip_prefix("file: ") {
+                    if uri.starts_with(&token) {
+                        candidates.push((uri.into(), true));
+                    }
+                }
+            }
+        }
+
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let (text, complete) = match &candidates[..] {
+            [] => return Ok(Vec::new()),
+            [(text, complete)] => (text.clone(), *complete),
+            [(first, _), xs @ ..] => {
+                let mut prefix = first.as_str();
+                for (x, _) in xs {
+                    let len = prefix
+                        .char_indices()
+                        .zip(x.chars())
+                        .find(|((_, c1), c2)| c1 != c2)
+                        .map_or(prefix.len().min(x.len()), |((i, _), _)| i);
+                    prefix = &prefix[.. len];
+                }
+                (String::from(prefix), false)
+            }
+        };
+
+        if text.chars().count() >= token.chars().count() {
+            line.truncate(start);
+            if quoted || text.contains(|c: char| c.is_whitespace() || c == '"') {
+                let text = quote(&text);
+                if complete {
+                    line.push_str(&text);
+                    line.push(' ');
+                } else {
+                    line.push_str(&text[.. text.len() - 1]);
+                }
+            } else {
+                line.push_str(&text);
+                if complete {
+                    line.push(' ');
+                }
+            }
+        }
+
+        Ok(if candidates.len() > 1 {
+            candidates.into_iter().map(|(x, _)| x).collect()
+        } else {
+            Vec::new()
+        })
+    }
+}
@@ -1,12 +1,15 @@
 use serde::{
-    de::{self, EnumAccess, SeqAccess, VariantAccess, Visitor},
+    de::{
+        self, value::MapAccessDeserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
     Deserialize, Deserializer,
 };
 use tui::style::Color;
 
 use std::fmt::{self, Formatter};
 
-use crate::defaults;
+use crate::{app::Command, defaults};
 
 #[derive(Deserialize)]
 pub struct Config {
@@ -16,16 +19,38 @@ pub struct Config {
     pub clear_query_on_play: bool,
     #[serde(default)]
     pub cycle: bool,
+    #[serde(default)]
+    pub fuzzy_search: bool,
+    #[serde(default = "defaults::history_size")]
+    pub history_size: usize,
     #[serde(default = "defaults::jump_lines")]
     pub jump_lines: usize,
+    #[serde(default)]
+    pub lazy_queue: bool,
+    #[serde(default = "defaults::key_timeout")]
+    pub key_timeout: f32,
+    #[serde(default = "defaults::message_timeout")]
+    pub message_timeout: f32,
     #[serde(default = "defaults::seek_secs")]
     pub seek_secs: f32,
     #[serde(default = "defaults::search_fields")]
     pub search_fields: SearchFields,
+    #[serde(default = "defaults::search_folding")]
+    pub search_folding: SearchFolding,
     #[serde(default = "defaults::ups")]
     pub ups: f32,
     #[serde(default = "defaults::layout")]
     pub layout: Widget,
+    #[serde(default)]
+    pub screens: Vec<Screen>,
+    #[serde(default = "defaults::help")]
+    pub help: Widget,
+}
+
+#[derive(Deserialize)]
+pub struct Screen {
+    pub name: String,
+    pub layout: Widget,
 }
 
 #[derive(Deserialize)]
@@ -40,10 +65,25 @@ pub struct SearchFields {
     pub album: bool,
 }
 
+#[derive(Deserialize)]
+pub struct SearchFolding {
+    #[serde(default = "yes")]
+    pub accents: bool,
+    #[serde(default = "yes")]
+    pub width: bool,
+    #[serde(default)]
+    pub kana: bool,
+}
+
 fn yes() -> bool {
     true
 }
 
+// the else branch of `Widget::If` is written without `Some`, like the one of `Texts::If`
+fn else_widget<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Box<Widget>>, D::Error> {
+    Widget::deserialize(de).map(|x| Some(Box::new(x)))
+}
+
 #[derive(Deserialize)]
 pub enum Widget {
     Rows(Vec<Constrained<Widget>>),
@@ -53,6 +93,104 @@ pub enum Widget {
     TextboxC(Texts),
     TextboxR(Texts),
     Queue(Vec<Column>),
+    Output,
+    Tabs(Texts),
+    Help(Vec<Column>),
+    Log(Texts),
+    Button(Command, Box<Widget>),
+    Seek(Box<Widget>),
+    Progress(Bar),
+    Gauge(Gauge, Bar),
+    If(
+        Condition,
+        Box<Widget>,
+        #[serde(default, deserialize_with = "else_widget")] Option<Box<Widget>>,
+    ),
+    Block {
+        #[serde(default = "defaults::borders")]
+        borders: Vec<Border>,
+        #[serde(default)]
+        border_type: BorderType,
+        #[serde(default = "defaults::title")]
+        title: Texts,
+        #[serde(default)]
+        style: Vec<AddStyle>,
+        #[serde(default)]
+        border_style: Vec<AddStyle>,
+        #[serde(default)]
+        margin: Sides,
+        #[serde(default)]
+        padding: Sides,
+        inner: Box<Widget>,
+    },
+}
+
+#[derive(Deserialize)]
+pub enum Border {
+    All,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+pub enum BorderType {
+    #[default]
+    Plain,
+    Rounded,
+    Double,
+    Thick,
+}
+
+#[derive(Default, Deserialize)]
+pub struct Sides {
+    #[serde(default)]
+    pub top: u16,
+    #[serde(default)]
+    pub bottom: u16,
+    #[serde(default)]
+    pub left: u16,
+    #[serde(default)]
+    pub right: u16,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+pub enum Gauge {
+    #[default]
+    Elapsed,
+    Volume,
+    QueuePosition,
+}
+
+#[derive(Deserialize)]
+pub struct Bar {
+    #[serde(default = "defaults::bar_fill")]
+    pub fill: String,
+    #[serde(default = "defaults::bar_empty")]
+    pub empty: String,
+    #[serde(default)]
+    pub head: String,
+    #[serde(default)]
+    pub fill_style: Vec<AddStyle>,
+    #[serde(default)]
+    pub empty_style: Vec<AddStyle>,
+    #[serde(default)]
+    pub head_style: Vec<AddStyle>,
+}
+
+#[derive(Deserialize)]
+pub struct TextBar {
+    #[serde(default)]
+    pub gauge: Gauge,
+    #[serde(default)]
+    pub width: Option<u16>,
+    #[serde(default = "defaults::bar_fill")]
+    pub fill: String,
+    #[serde(default = "defaults::bar_empty")]
+    pub empty: String,
+    #[serde(default)]
+    pub head: String,
 }
 
 #[derive(Deserialize)]
@@ -77,6 +215,19 @@ pub enum Texts {
     QueueArtist,
     QueueAlbum,
     Query,
+    PendingKeys,
+    CommandLine,
+    MatchIndex,
+    MatchCount,
+    QueryError,
+    ScreenName,
+    HelpMode,
+    HelpKeys,
+    HelpCommand,
+    HelpDescription,
+    HelpQuery,
+    Message,
+    Bar(TextBar),
     Styled(Vec<AddStyle>, Box<Texts>),
     Parts(Vec<Texts>),
     If(Condition, Box<Texts>, Option<Box<Texts>>),
@@ -123,6 +274,19 @@ pub enum Condition {
     Selected,
     Searching,
     Filtered,
+    Finding,
+    Commanding,
+    OutputExist,
+    Marked,
+    Visual,
+    ScreenCurrent,
+    Focused,
+    HelpSearching,
+    MessageExist,
+    QueryErrorExist,
+    MessageError,
+    WidthAtLeast(u16),
+    HeightAtLeast(u16),
     Not(Box<Condition>),
     And(Box<Condition>, Box<Condition>),
     Or(Box<Condition>, Box<Condition>),
@@ -136,8 +300,12 @@ pub struct Column {
     pub style: Vec<AddStyle>,
     #[serde(default)]
     pub selected_style: Vec<AddStyle>,
+    #[serde(default)]
+    pub match_style: Vec<AddStyle>,
 }
 
+const BAR_FIELDS: &[&str] = &["gauge", "width", "fill", "empty", "head"];
+
 impl<'de> Deserialize<'de> for Texts {
     fn deserialize<D>(de: D) -> Result<Self, D::Error>
     where
@@ -168,6 +336,19 @@ impl<'de> Deserialize<'de> for Texts {
                     QueueArtist,
                     QueueAlbum,
                     Query,
+                    PendingKeys,
+                    CommandLine,
+                    MatchIndex,
+                    MatchCount,
+                    QueryError,
+                    ScreenName,
+                    HelpMode,
+                    HelpKeys,
+                    HelpCommand,
+                    HelpDescription,
+                    HelpQuery,
+                    Message,
+                    Bar,
                     Styled,
                     Parts,
                     If,
@@ -194,6 +375,19 @@ impl<'de> Deserialize<'de> for Texts {
                     }
                 }
 
+                struct BarVisitor;
+                impl<'de> Visitor<'de> for BarVisitor {
+                    type Value = Texts;
+
+                    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+                        formatter.write_str("variant Bar")
+                    }
+
+                    fn visit_map<A: MapAccess<'de>>(self, ma: A) -> Result<Self::Value, A::Error> {
+                        TextBar::deserialize(MapAccessDeserializer::new(ma)).map(Texts::Bar)
+                    }
+                }
+
                 struct IfVisitor;
                 impl<'de> Visitor<'de> for IfVisitor {
                     type Value = Texts;
@@ -241,6 +435,19 @@ impl<'de> Deserialize<'de> for Texts {
                     Variant::QueueArtist => unit_variant!(QueueArtist),
                     Variant::QueueAlbum => unit_variant!(QueueAlbum),
                     Variant::Query => unit_variant!(Query),
+                    Variant::PendingKeys => unit_variant!(PendingKeys),
+                    Variant::CommandLine => unit_variant!(CommandLine),
+                    Variant::MatchIndex => unit_variant!(MatchIndex),
+                    Variant::MatchCount => unit_variant!(MatchCount),
+                    Variant::QueryError => unit_variant!(QueryError),
+                    Variant::ScreenName => unit_variant!(ScreenName),
+                    Variant::HelpMode => unit_variant!(HelpMode),
+                    Variant::HelpKeys => unit_variant!(HelpKeys),
+                    Variant::HelpCommand => unit_variant!(HelpCommand),
+                    Variant::HelpDescription => unit_variant!(HelpDescription),
+                    Variant::HelpQuery => unit_variant!(HelpQuery),
+                    Variant::Message => unit_variant!(Message),
+                    Variant::Bar => va.struct_variant(BAR_FIELDS, BarVisitor),
                     Variant::Styled => va.tuple_variant(2, StyledVisitor),
                     Variant::Parts => Ok(Texts::Parts(va.newtype_variant()?)),
                     Variant::If => va.tuple_variant(3, IfVisitor),
@@ -264,6 +471,19 @@ impl<'de> Deserialize<'de> for Texts {
                 "QueueArtist",
                 "QueueAlbum",
                 "Query",
+                "PendingKeys",
+                "CommandLine",
+                "MatchIndex",
+                "MatchCount",
+                "QueryError",
+                "ScreenName",
+                "HelpMode",
+                "HelpKeys",
+                "HelpCommand",
+                "HelpDescription",
+                "HelpQuery",
+                "Message",
+                "Bar",
                 "Styled",
                 "Parts",
                 "If",
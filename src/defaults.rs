@@ -1,7 +1,8 @@
 use tui::style::Color;
 
 use crate::config::{
-    AddStyle, Column, Condition, Config, Constrained, SearchFields, Texts, Widget,
+    AddStyle, Border, Column, Condition, Config, Constrained, SearchFields, SearchFolding, Texts,
+    Widget,
 };
 
 pub fn config() -> Config {
@@ -9,11 +10,19 @@ pub fn config() -> Config {
         address: address(),
         clear_query_on_play: false,
         cycle: false,
+        fuzzy_search: false,
+        history_size: history_size(),
         jump_lines: jump_lines(),
+        lazy_queue: false,
+        key_timeout: key_timeout(),
+        message_timeout: message_timeout(),
         seek_secs: seek_secs(),
         search_fields: search_fields(),
+        search_folding: search_folding(),
         ups: ups(),
         layout: layout(),
+        screens: Vec::new(),
+        help: help(),
     }
 }
 
@@ -21,10 +30,34 @@ pub fn address() -> String {
     String::from("127.0.0.1:6600")
 }
 
+pub fn bar_fill() -> String {
+    String::from("━")
+}
+
+pub fn bar_empty() -> String {
+    String::from("─")
+}
+
+pub fn borders() -> Vec<Border> {
+    vec![Border::All]
+}
+
+pub fn history_size() -> usize {
+    1000
+}
+
 pub fn jump_lines() -> usize {
     24
 }
 
+pub fn key_timeout() -> f32 {
+    1.0
+}
+
+pub fn message_timeout() -> f32 {
+    5.0
+}
+
 pub fn seek_secs() -> f32 {
     5.0
 }
@@ -38,6 +71,18 @@ pub fn search_fields() -> SearchFields {
     }
 }
 
+pub fn search_folding() -> SearchFolding {
+    SearchFolding {
+        accents: true,
+        width: true,
+        kana: false,
+    }
+}
+
+pub fn title() -> Texts {
+    Texts::Parts(Vec::new())
+}
+
 pub fn ups() -> f32 {
     1.0
 }
@@ -83,14 +128,21 @@ pub fn layout() -> Widget {
                 Column {
                     item: Constrained::Ratio(
                         12,
-                        Texts::If(
-                            Condition::QueueCurrent,
-                            Box::new(Texts::Styled(
-                                vec![AddStyle::Italic],
-                                Box::new(Texts::QueueTitle),
-                            )),
-                            Some(Box::new(Texts::QueueTitle)),
-                        ),
+                        Texts::Parts(vec![
+                            Texts::If(
+                                Condition::Marked,
+                                Box::new(Texts::Text(String::from("+ "))),
+                                None,
+                            ),
+                            Texts::If(
+                                Condition::QueueCurrent,
+                                Box::new(Texts::Styled(
+                                    vec![AddStyle::Italic],
+                                    Box::new(Texts::QueueTitle),
+                                )),
+                                Some(Box::new(Texts::QueueTitle)),
+                            ),
+                        ]),
                     ),
                     style: vec![AddStyle::Fg(Color::Indexed(75))],
                     selected_style: vec![
@@ -98,6 +150,7 @@ pub fn layout() -> Widget {
                         AddStyle::Bg(Color::Indexed(75)),
                         AddStyle::Bold,
                     ],
+                    match_style: vec![AddStyle::Fg(Color::Indexed(203)), AddStyle::Bold],
                 },
                 Column {
                     item: Constrained::Ratio(
@@ -117,6 +170,7 @@ pub fn layout() -> Widget {
                         AddStyle::Bg(Color::Indexed(111)),
                         AddStyle::Bold,
                     ],
+                    match_style: vec![AddStyle::Fg(Color::Indexed(203)), AddStyle::Bold],
                 },
                 Column {
                     item: Constrained::Ratio(
@@ -136,6 +190,7 @@ pub fn layout() -> Widget {
                         AddStyle::Bg(Color::Indexed(147)),
                         AddStyle::Bold,
                     ],
+                    match_style: vec![AddStyle::Fg(Color::Indexed(203)), AddStyle::Bold],
                 },
                 Column {
                     item: Constrained::Ratio(
@@ -155,9 +210,14 @@ pub fn layout() -> Widget {
                         AddStyle::Bg(Color::Indexed(183)),
                         AddStyle::Bold,
                     ],
+                    match_style: Vec::new(),
                 },
             ]),
         ),
+        Constrained::Fixed(
+            8,
+            Widget::If(Condition::OutputExist, Box::new(Widget::Output), None),
+        ),
         Constrained::Fixed(
             1,
             Widget::Columns(vec![
@@ -166,93 +226,165 @@ pub fn layout() -> Widget {
                     Widget::Textbox(Texts::Styled(
                         vec![AddStyle::Bold],
                         Box::new(Texts::If(
-                            Condition::Searching,
+                            Condition::Or(
+                                Box::new(Condition::Searching),
+                                Box::new(Condition::Commanding),
+                            ),
                             Box::new(Texts::Parts(vec![
                                 Texts::Styled(
                                     vec![AddStyle::Fg(Color::Indexed(113))],
-                                    Box::new(Texts::Text(String::from("Searching: "))),
+                                    Box::new(Texts::If(
+                                        Condition::Searching,
+                                        Box::new(Texts::If(
+                                            Condition::Finding,
+                                            Box::new(Texts::Text(String::from("Find: "))),
+                                            Some(Box::new(Texts::Text(String::from(
+                                                "Searching: ",
+                                            )))),
+                                        )),
+                                        Some(Box::new(Texts::Text(String::from(":")))),
+                                    )),
                                 ),
                                 Texts::Styled(
                                     vec![AddStyle::Fg(Color::Indexed(185))],
-                                    Box::new(Texts::Query),
+                                    Box::new(Texts::If(
+                                        Condition::Searching,
+                                        Box::new(Texts::Query),
+                                        Some(Box::new(Texts::Parts(vec![
+                                            Texts::CommandLine,
+                                            Texts::Text(String::from("⎸")),
+                                        ]))),
+                                    )),
                                 ),
-                                Texts::Styled(
-                                    vec![AddStyle::Fg(Color::Indexed(185))],
-                                    Box::new(Texts::Text(String::from("⎸"))),
-                                ),
-                            ])),
-                            Some(Box::new(Texts::If(
-                                Condition::Not(Box::new(Condition::Stopped)),
-                                Box::new(Texts::Parts(vec![
-                                    Texts::Styled(
-                                        vec![AddStyle::Fg(Color::Indexed(113))],
+                                Texts::If(
+                                    Condition::And(
+                                        Box::new(Condition::Searching),
+                                        Box::new(Condition::Finding),
+                                    ),
+                                    Box::new(Texts::Styled(
+                                        vec![AddStyle::Fg(Color::Indexed(81))],
                                         Box::new(Texts::Parts(vec![
-                                            Texts::If(
-                                                Condition::Playing,
-                                                Box::new(Texts::Text(String::from("[playing: "))),
-                                                Some(Box::new(Texts::Text(String::from(
-                                                    "[paused:  ",
-                                                )))),
-                                            ),
-                                            Texts::CurrentElapsed,
+                                            Texts::Text(String::from("  [")),
+                                            Texts::MatchIndex,
                                             Texts::Text(String::from("/")),
-                                            Texts::CurrentDuration,
-                                            Texts::Text(String::from("] ")),
+                                            Texts::MatchCount,
+                                            Texts::Text(String::from("]")),
                                         ])),
+                                    )),
+                                    None,
+                                ),
+                                Texts::If(
+                                    Condition::And(
+                                        Box::new(Condition::Searching),
+                                        Box::new(Condition::QueryErrorExist),
                                     ),
-                                    Texts::If(
-                                        Condition::TitleExist,
+                                    Box::new(Texts::Styled(
+                                        vec![AddStyle::Fg(Color::Indexed(203))],
                                         Box::new(Texts::Parts(vec![
-                                            Texts::Styled(
-                                                vec![AddStyle::Fg(Color::Indexed(149))],
-                                                Box::new(Texts::CurrentTitle),
-                                            ),
-                                            Texts::If(
-                                                Condition::ArtistExist,
-                                                Box::new(Texts::Parts(vec![
-                                                    Texts::Styled(
-                                                        vec![AddStyle::Fg(Color::Indexed(216))],
-                                                        Box::new(Texts::Text(String::from(" ◆ "))),
-                                                    ),
-                                                    Texts::Styled(
-                                                        vec![AddStyle::Fg(Color::Indexed(185))],
-                                                        Box::new(Texts::CurrentArtist),
-                                                    ),
-                                                    Texts::If(
-                                                        Condition::AlbumExist,
-                                                        Box::new(Texts::Parts(vec![
-                                                            Texts::Styled(
-                                                                vec![AddStyle::Fg(Color::Indexed(
-                                                                    216,
-                                                                ))],
-                                                                Box::new(Texts::Text(
-                                                                    String::from(" ◆ "),
-                                                                )),
-                                                            ),
-                                                            Texts::Styled(
-                                                                vec![AddStyle::Fg(Color::Indexed(
-                                                                    221,
-                                                                ))],
-                                                                Box::new(Texts::CurrentAlbum),
-                                                            ),
-                                                        ])),
-                                                        None,
-                                                    ),
-                                                ])),
-                                                None,
-                                            ),
+                                            Texts::Text(String::from("  ")),
+                                            Texts::QueryError,
                                         ])),
-                                        Some(Box::new(Texts::Styled(
-                                            vec![AddStyle::Fg(Color::Indexed(185))],
-                                            Box::new(Texts::CurrentFile),
-                                        ))),
-                                    ),
-                                ])),
-                                None,
+                                    )),
+                                    None,
+                                ),
+                            ])),
+                            Some(Box::new(Texts::If(
+                                Condition::MessageExist,
+                                Box::new(Texts::If(
+                                    Condition::MessageError,
+                                    Box::new(Texts::Styled(
+                                        vec![AddStyle::Fg(Color::Indexed(203))],
+                                        Box::new(Texts::Message),
+                                    )),
+                                    Some(Box::new(Texts::Styled(
+                                        vec![AddStyle::Fg(Color::Indexed(113))],
+                                        Box::new(Texts::Message),
+                                    ))),
+                                )),
+                                Some(Box::new(Texts::If(
+                                    Condition::Not(Box::new(Condition::Stopped)),
+                                    Box::new(Texts::Parts(vec![
+                                        Texts::Styled(
+                                            vec![AddStyle::Fg(Color::Indexed(113))],
+                                            Box::new(Texts::Parts(vec![
+                                                Texts::If(
+                                                    Condition::Playing,
+                                                    Box::new(Texts::Text(String::from(
+                                                        "[playing: ",
+                                                    ))),
+                                                    Some(Box::new(Texts::Text(String::from(
+                                                        "[paused:  ",
+                                                    )))),
+                                                ),
+                                                Texts::CurrentElapsed,
+                                                Texts::Text(String::from("/")),
+                                                Texts::CurrentDuration,
+                                                Texts::Text(String::from("] ")),
+                                            ])),
+                                        ),
+                                        Texts::If(
+                                            Condition::TitleExist,
+                                            Box::new(Texts::Parts(vec![
+                                                Texts::Styled(
+                                                    vec![AddStyle::Fg(Color::Indexed(149))],
+                                                    Box::new(Texts::CurrentTitle),
+                                                ),
+                                                Texts::If(
+                                                    Condition::ArtistExist,
+                                                    Box::new(Texts::Parts(vec![
+                                                        Texts::Styled(
+                                                            vec![AddStyle::Fg(Color::Indexed(216))],
+                                                            Box::new(Texts::Text(String::from(
+                                                                " ◆ ",
+                                                            ))),
+                                                        ),
+                                                        Texts::Styled(
+                                                            vec![AddStyle::Fg(Color::Indexed(185))],
+                                                            Box::new(Texts::CurrentArtist),
+                                                        ),
+                                                        Texts::If(
+                                                            Condition::AlbumExist,
+                                                            Box::new(Texts::Parts(vec![
+                                                                Texts::Styled(
+                                                                    vec![AddStyle::Fg(
+                                                                        Color::Indexed(216),
+                                                                    )],
+                                                                    Box::new(Texts::Text(
+                                                                        String::from(" ◆ "),
+                                                                    )),
+                                                                ),
+                                                                Texts::Styled(
+                                                                    vec![AddStyle::Fg(
+                                                                        Color::Indexed(221),
+                                                                    )],
+                                                                    Box::new(Texts::CurrentAlbum),
+                                                                ),
+                                                            ])),
+                                                            None,
+                                                        ),
+                                                    ])),
+                                                    None,
+                                                ),
+                                            ])),
+                                            Some(Box::new(Texts::Styled(
+                                                vec![AddStyle::Fg(Color::Indexed(185))],
+                                                Box::new(Texts::CurrentFile),
+                                            ))),
+                                        ),
+                                    ])),
+                                    None,
+                                ))),
                             ))),
                         )),
                     )),
                 ),
+                Constrained::Fixed(
+                    6,
+                    Widget::TextboxR(Texts::Styled(
+                        vec![AddStyle::Fg(Color::Indexed(185))],
+                        Box::new(Texts::PendingKeys),
+                    )),
+                ),
                 Constrained::Fixed(
                     7,
                     Widget::TextboxR(Texts::Styled(
@@ -291,3 +423,65 @@ pub fn layout() -> Widget {
         ),
     ])
 }
+
+pub fn help() -> Widget {
+    Widget::Rows(vec![
+        Constrained::Fixed(
+            1,
+            Widget::Textbox(Texts::Styled(
+                vec![AddStyle::Bold],
+                Box::new(Texts::Parts(vec![
+                    Texts::Styled(
+                        vec![AddStyle::Fg(Color::Indexed(113))],
+                        Box::new(Texts::If(
+                            Condition::HelpSearching,
+                            Box::new(Texts::Text(String::from("Searching: "))),
+                            Some(Box::new(Texts::Text(String::from("Help ")))),
+                        )),
+                    ),
+                    Texts::Styled(
+                        vec![AddStyle::Fg(Color::Indexed(185))],
+                        Box::new(Texts::HelpQuery),
+                    ),
+                    Texts::If(
+                        Condition::HelpSearching,
+                        Box::new(Texts::Styled(
+                            vec![AddStyle::Fg(Color::Indexed(185))],
+                            Box::new(Texts::Text(String::from("⎸"))),
+                        )),
+                        None,
+                    ),
+                ])),
+            )),
+        ),
+        Constrained::Min(
+            0,
+            Widget::Help(vec![
+                Column {
+                    item: Constrained::Fixed(8, Texts::HelpMode),
+                    style: vec![AddStyle::Fg(Color::Indexed(147))],
+                    selected_style: Vec::new(),
+                    match_style: Vec::new(),
+                },
+                Column {
+                    item: Constrained::Ratio(3, Texts::HelpKeys),
+                    style: vec![AddStyle::Fg(Color::Indexed(185))],
+                    selected_style: Vec::new(),
+                    match_style: Vec::new(),
+                },
+                Column {
+                    item: Constrained::Ratio(2, Texts::HelpCommand),
+                    style: vec![AddStyle::Fg(Color::Indexed(75))],
+                    selected_style: Vec::new(),
+                    match_style: Vec::new(),
+                },
+                Column {
+                    item: Constrained::Ratio(4, Texts::HelpDescription),
+                    style: vec![AddStyle::Fg(Color::Indexed(158))],
+                    selected_style: Vec::new(),
+                    match_style: Vec::new(),
+                },
+            ]),
+        ),
+    ])
+}
@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+#[cfg(unix)]
+use async_io::Async;
+use async_io::Timer;
+use crossterm::event::{self, EventStream};
+#[cfg(unix)]
+use futures_lite::AsyncReadExt;
+use futures_lite::{stream, Future, Stream, StreamExt};
+#[cfg(unix)]
+use signal_hook::{
+    consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP},
+    low_level::pipe,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::{ffi::c_int, time::Duration};
+
+use crate::mpd::Client;
+
+pub enum Event {
+    Input(event::Event),
+    // whether the status and the queue have changed
+    Idle(bool, bool),
+    Tick,
+    #[cfg(unix)]
+    Signal(c_int),
+    // the pending keys or the message expired
+    Timeout,
+}
+
+// every source of events merged into one stream, polled fairly
+pub fn stream(
+    idle_cl: Client,
+    update_interval: Duration,
+) -> Result<impl Stream<Item = Result<Event>>> {
+    let input = EventStream::new().map(|ev| {
+        ev.map(Event::Input)
+            .context("Failed to read terminal events")
+    });
+
+    let idle = stream::unfold(idle_cl, |mut cl| async move {
+        let ev = cl
+            .idle()
+            .await
+            .map(|(status, queue)| Event::Idle(status, queue));
+        Some((ev, cl))
+    });
+
+    let tick = Timer::interval(update_interval).map(|_| Ok(Event::Tick));
+
+    let events = input.race(idle).race(tick);
+
+    #[cfg(unix)]
+    let events = events.race(
+        signal(SIGTSTP)?
+            .race(signal(SIGCONT)?)
+            .map(|sig| sig.map(Event::Signal)),
+    );
+
+    Ok(events)
+}
+
+// completes with the signal once mmtc is asked to terminate, meant to be raced against everything
+// else so mmtc also quits while waiting for mpd
+#[cfg(unix)]
+pub fn terminated() -> Result<impl Future<Output = Result<c_int>>> {
+    let mut signals = Box::pin(signal(SIGTERM)?.race(signal(SIGHUP)?).race(signal(SIGINT)?));
+    Ok(async move { signals.next().await.context("Failed to receive signal")? })
+}
+
+#[cfg(not(unix))]
+pub fn terminated() -> Result<impl Future<Output = Result<c_int>>> {
+    Ok(futures_lite::future::pending())
+}
+
+// the handler writes to one end of the socket for every signal received, and the other end is read
+#[cfg(unix)]
+fn signal(sig: c_int) -> Result<impl Stream<Item = Result<c_int>>> {
+    let (rx, tx) = UnixStream::pair().context("Failed to create socket for signals")?;
+    pipe::register(sig, tx)
+        .with_context(|| format!("Failed to register handler for signal {sig}"))?;
+    let rx = Async::new(rx).context("Failed to create socket for signals")?;
+
+    Ok(stream::unfold(rx, move |mut rx| async move {
+        let sig = rx
+            .read(&mut [0])
+            .await
+            .map(|_| sig)
+            .context("Failed to receive signal");
+        Some((sig, rx))
+    }))
+}
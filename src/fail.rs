@@ -8,3 +8,4 @@ macro_rules! fail {
 
 fail!(parse_cfg path = "Failed to parse configuration file {}");
 fail!(read path = "Failed to read file {}");
+fail!(write path = "Failed to write file {}");
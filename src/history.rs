@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use dirs::{data_local_dir, state_dir};
+
+use std::{collections::HashSet, fs, io::ErrorKind, path::PathBuf};
+
+use crate::fail;
+
+// input history of a prompt, saved to a file under the state directory
+pub struct History {
+    path: Option<PathBuf>,
+    size: usize,
+    entries: Vec<String>,
+    pos: usize,
+    draft: String,
+}
+
+impl History {
+    pub fn load(name: &str, size: usize) -> Result<History> {
+        let path = state_dir().or_else(data_local_dir).map(|mut path| {
+            path.push("mmtc");
+            path.push(name);
+            path
+        });
+
+        let mut entries = Vec::new();
+        if let Some(path) = &path {
+            match fs::read_to_string(path) {
+                Ok(xs) => {
+                    // keep the last occurrence of each entry
+                    let mut seen = HashSet::new();
+                    entries.extend(
+                        xs.lines()
+                            .rev()
+                            .filter(|x| seen.insert(*x))
+                            .take(size)
+                            .map(String::from),
+                    );
+                    entries.reverse();
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(fail::read(path.display())),
+            }
+        }
+
+        Ok(History {
+            path,
+            size,
+            pos: entries.len(),
+            entries,
+            draft: String::new(),
+        })
+    }
+
+    pub fn push(&mut self, entry: &str) -> Result<()> {
+        self.reset();
+        if entry.is_empty() || self.size == 0 {
+            return Ok(());
+        }
+
+        self.entries.retain(|x| x != entry);
+        if self.entries.len() >= self.size {
+            self.entries.drain(.. self.entries.len() + 1 - self.size);
+        }
+        self.entries.push(entry.into());
+        self.pos = self.entries.len();
+
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).with_context(fail::write(dir.display()))?;
+            }
+            let mut xs = self.entries.join("\n");
+            xs.push('\n');
+            fs::write(path, xs).with_context(fail::write(path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        if self.pos == 0 {
+            return None;
+        }
+        if self.pos == self.entries.len() {
+            self.draft = current.into();
+        }
+        self.pos -= 1;
+        Some(&self.entries[self.pos])
+    }
+
+    pub fn next(&mut self) -> Option<&str> {
+        if self.pos >= self.entries.len() {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.entries.get(self.pos).unwrap_or(&self.draft))
+    }
+
+    pub fn reset(&mut self) {
+        self.pos = self.entries.len();
+        self.draft.clear();
+    }
+}
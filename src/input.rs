@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+// a single line of text with a cursor, moved by grapheme clusters
+#[derive(Default)]
+pub struct LineInput {
+    text: String,
+    // byte offset into text
+    cursor: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Edit {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Start,
+    End,
+    Delete,
+    DeleteWord,
+}
+
+impl Edit {
+    pub fn description(self) -> &'static str {
+        match self {
+            Edit::Left => "move the cursor left",
+            Edit::Right => "move the cursor right",
+            Edit::WordLeft => "move the cursor to the start of the word",
+            Edit::WordRight => "move the cursor to the end of the word",
+            Edit::Start => "move the cursor to the start",
+            Edit::End => "move the cursor to the end",
+            Edit::Delete => "delete the character under the cursor",
+            Edit::DeleteWord => "delete the word before the cursor",
+        }
+    }
+}
+
+fn is_word(x: &str) -> bool {
+    x.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+impl LineInput {
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    // the text before the cursor, the grapheme under the cursor, and the text after it
+    pub fn split(&self) -> (&str, &str, &str) {
+        let (before, after) = self.text.split_at(self.cursor);
+        let cursor = after.graphemes(true).next().unwrap_or_default();
+        (before, cursor, &after[cursor.len() ..])
+    }
+
+    pub fn set(&mut self, x: &str) {
+        self.text.clear();
+        self.text.push_str(x);
+        self.cursor = self.text.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    // insert pasted text, replacing line breaks and other control characters with spaces
+    pub fn insert_str(&mut self, x: &str) {
+        let x: String = x
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        self.text.insert_str(self.cursor, &x);
+        self.cursor += x.len();
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.previous();
+        self.text.replace_range(start .. self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn edit(&mut self, edit: Edit) {
+        match edit {
+            Edit::Left => self.cursor = self.previous(),
+            Edit::Right => self.cursor = self.next(),
+            Edit::WordLeft => self.cursor = self.word_start(),
+            Edit::WordRight => self.cursor = self.word_end(),
+            Edit::Start => self.cursor = 0,
+            Edit::End => self.cursor = self.text.len(),
+            Edit::Delete => {
+                let end = self.next();
+                self.text.replace_range(self.cursor .. end, "");
+            }
+            Edit::DeleteWord => {
+                let start = self.word_start();
+                self.text.replace_range(start .. self.cursor, "");
+                self.cursor = start;
+            }
+        }
+    }
+
+    fn previous(&self) -> usize {
+        self.text[.. self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next(&self) -> usize {
+        self.text[self.cursor ..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |x| self.cursor + x.len())
+    }
+
+    // skip the separators before the cursor, then the word before them
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        let mut word = false;
+        for (i, x) in self.text[.. self.cursor].grapheme_indices(true).rev() {
+            if is_word(x) {
+                word = true;
+            } else if word {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    // skip the separators after the cursor, then the word after them
+    fn word_end(&self) -> usize {
+        let mut end = self.cursor;
+        let mut word = false;
+        for (i, x) in self.text[self.cursor ..].grapheme_indices(true) {
+            if is_word(x) {
+                word = true;
+            } else if word {
+                break;
+            }
+            end = self.cursor + i + x.len();
+        }
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(x: &str) -> LineInput {
+        let mut input = LineInput::default();
+        input.set(x);
+        input
+    }
+
+    #[test]
+    fn cursor() {
+        let mut x = input("abc");
+        assert_eq!(x.split(), ("abc", "", ""));
+        x.edit(Edit::Right);
+        assert_eq!(x.split(), ("abc", "", ""));
+        x.edit(Edit::Left);
+        assert_eq!(x.split(), ("ab", "c", ""));
+        x.edit(Edit::Start);
+        assert_eq!(x.split(), ("", "a", "bc"));
+        x.edit(Edit::Left);
+        assert_eq!(x.split(), ("", "a", "bc"));
+        x.insert('x');
+        assert_eq!(x.split(), ("x", "a", "bc"));
+        x.edit(Edit::End);
+        assert_eq!(x.split(), ("xabc", "", ""));
+    }
+
+    #[test]
+    fn words() {
+        let mut x = input("pink  floyd - time");
+        x.edit(Edit::WordLeft);
+        assert_eq!(x.split(), ("pink  floyd - ", "t", "ime"));
+        x.edit(Edit::WordLeft);
+        assert_eq!(x.split(), ("pink  ", "f", "loyd - time"));
+        x.edit(Edit::WordRight);
+        assert_eq!(x.split(), ("pink  floyd", " ", "- time"));
+        x.edit(Edit::WordRight);
+        assert_eq!(x.split(), ("pink  floyd - time", "", ""));
+
+        x.edit(Edit::DeleteWord);
+        assert_eq!(x.split(), ("pink  floyd - ", "", ""));
+        x.edit(Edit::DeleteWord);
+        assert_eq!(x.split(), ("pink  ", "", ""));
+        x.edit(Edit::DeleteWord);
+        assert_eq!(x.split(), ("", "", ""));
+        x.edit(Edit::DeleteWord);
+        assert!(x.is_empty());
+    }
+
+    #[test]
+    fn multibyte() {
+        // a combining accent, a wide character, and a flag made of two code points
+        let mut x = input("e\u{301}日🇯🇵");
+        x.edit(Edit::Left);
+        assert_eq!(x.split(), ("e\u{301}日", "🇯🇵", ""));
+        x.edit(Edit::Left);
+        assert_eq!(x.split(), ("e\u{301}", "日", "🇯🇵"));
+        x.edit(Edit::Left);
+        assert_eq!(x.split(), ("", "e\u{301}", "日🇯🇵"));
+
+        x.edit(Edit::Delete);
+        assert_eq!(x.split(), ("", "日", "🇯🇵"));
+        x.edit(Edit::End);
+        x.backspace();
+        assert_eq!(x.split(), ("日", "", ""));
+        x.insert('é');
+        x.edit(Edit::DeleteWord);
+        assert!(x.is_empty());
+    }
+
+    #[test]
+    fn paste() {
+        let mut x = input("ab");
+        x.edit(Edit::Left);
+        x.insert_str("x\ny\tz");
+        assert_eq!(x.split(), ("ax y z", "b", ""));
+        assert_eq!(x.as_str(), "ax y zb");
+        x.clear();
+        assert_eq!(x.split(), ("", "", ""));
+    }
+}
@@ -0,0 +1,477 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use std::{
+    cmp::min,
+    fmt::{self, Display, Formatter},
+    mem,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    app::{Command, Modal, State},
+    cmdline::ACTIONS,
+    input::Edit,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+pub type Bindings = &'static [(&'static [Key], Command)];
+
+// the largest count, so repeating a command stays cheap and adding it to a position can't overflow
+const MAX_COUNT: usize = 99_999;
+
+pub struct Keys {
+    timeout: Duration,
+    pending: Vec<Key>,
+    count: Option<usize>,
+    deadline: Option<Instant>,
+}
+
+const fn ch(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: false,
+        alt: false,
+    }
+}
+
+const fn ctrl(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: true,
+        alt: false,
+    }
+}
+
+const fn alt(c: char) -> Key {
+    Key {
+        code: KeyCode::Char(c),
+        ctrl: false,
+        alt: true,
+    }
+}
+
+const fn key(code: KeyCode) -> Key {
+    Key {
+        code,
+        ctrl: false,
+        alt: false,
+    }
+}
+
+pub const BINDINGS: Bindings = &[
+    (&[ch('q')], Command::Quit),
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ch('r')], Command::ToggleRepeat),
+    (&[ch('R')], Command::ToggleRandom),
+    (&[ch('s')], Command::ToggleSingle),
+    (&[ch('S')], Command::ToggleOneshot),
+    (&[ch('c')], Command::ToggleConsume),
+    (&[ch('p')], Command::TogglePause),
+    (&[ch(';')], Command::Stop),
+    (&[ch('h')], Command::SeekBackwards),
+    (&[key(KeyCode::Left)], Command::SeekBackwards),
+    (&[ch('l')], Command::SeekForwards),
+    (&[key(KeyCode::Right)], Command::SeekForwards),
+    (&[ch('H')], Command::Previous),
+    (&[ch('L')], Command::Next),
+    (&[key(KeyCode::Enter)], Command::Play),
+    (&[ch('d'), ch('d')], Command::Delete),
+    (&[ch(' ')], Command::Reselect),
+    (&[ch('j')], Command::Down),
+    (&[key(KeyCode::Down)], Command::Down),
+    (&[ch('k')], Command::Up),
+    (&[key(KeyCode::Up)], Command::Up),
+    (&[ch('J')], Command::JumpDown),
+    (&[ctrl('d')], Command::JumpDown),
+    (&[key(KeyCode::PageDown)], Command::JumpDown),
+    (&[ch('K')], Command::JumpUp),
+    (&[ctrl('u')], Command::JumpUp),
+    (&[key(KeyCode::PageUp)], Command::JumpUp),
+    (&[ch('g'), ch('g')], Command::GotoTop),
+    (&[ch('G')], Command::GotoBottom),
+    (&[ch('g'), ch('t')], Command::NextScreen),
+    (&[ch('g'), ch('T')], Command::PreviousScreen),
+    (&[key(KeyCode::Tab)], Command::FocusNext),
+    (&[key(KeyCode::BackTab)], Command::FocusPrevious),
+    (&[ch('/')], Command::Searching(true)),
+    (&[ch('f')], Command::Find),
+    (&[ch('n')], Command::NextMatch),
+    (&[ch('N')], Command::PreviousMatch),
+    (&[ch(':')], Command::Commanding(true)),
+    (&[ch('?')], Command::Help),
+    (&[ch('m')], Command::ToggleMark),
+    (&[ch('M')], Command::MarkAll),
+    (&[ch('U')], Command::ClearMarks),
+    (&[ch('v')], Command::ToggleVisual),
+    (&[ch('V')], Command::ToggleVisual),
+    (&[alt('k')], Command::MoveUp),
+    (&[alt('j')], Command::MoveDown),
+    (&[ch('P')], Command::Priority),
+    (&[ch('a')], Command::AddToPlaylist(String::new())),
+    (&[ch('w')], Command::Save(String::new())),
+    (&[ch('C')], Command::Clear),
+    (&[key(KeyCode::Esc)], Command::QuitSearch),
+];
+
+// bindings in visual mode that take precedence over the ones above
+pub const VISUAL_BINDINGS: Bindings = &[
+    (&[ch('d')], Command::Delete),
+    (&[key(KeyCode::Esc)], Command::QuitVisual),
+];
+
+// bindings while the command output is shown outside of command mode, taking precedence over the
+// normal ones but not the visual ones
+pub const OUTPUT_BINDINGS: Bindings = &[
+    (&[key(KeyCode::PageUp)], Command::ScrollOutputUp),
+    (&[key(KeyCode::PageDown)], Command::ScrollOutputDown),
+    (&[key(KeyCode::Esc)], Command::ClearOutput),
+];
+
+// bindings in the help popup
+pub const HELP_BINDINGS: Bindings = &[
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ch('q')], Command::QuitHelp),
+    (&[ch('?')], Command::QuitHelp),
+    (&[key(KeyCode::Esc)], Command::QuitHelp),
+    (&[ch('j')], Command::Down),
+    (&[key(KeyCode::Down)], Command::Down),
+    (&[ch('k')], Command::Up),
+    (&[key(KeyCode::Up)], Command::Up),
+    (&[ch('J')], Command::JumpDown),
+    (&[ctrl('d')], Command::JumpDown),
+    (&[key(KeyCode::PageDown)], Command::JumpDown),
+    (&[ch('K')], Command::JumpUp),
+    (&[ctrl('u')], Command::JumpUp),
+    (&[key(KeyCode::PageUp)], Command::JumpUp),
+    (&[ch('g'), ch('g')], Command::GotoTop),
+    (&[ch('G')], Command::GotoBottom),
+    (&[ch('/')], Command::Searching(true)),
+];
+
+// bindings in confirm and pick-from-list dialogs
+pub const MODAL_BINDINGS: Bindings = &[
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ch('y')], Command::ConfirmModal),
+    (&[key(KeyCode::Enter)], Command::ConfirmModal),
+    (&[ch('n')], Command::QuitModal),
+    (&[ch('q')], Command::QuitModal),
+    (&[key(KeyCode::Esc)], Command::QuitModal),
+    (&[ch('j')], Command::Down),
+    (&[key(KeyCode::Down)], Command::Down),
+    (&[ch('k')], Command::Up),
+    (&[key(KeyCode::Up)], Command::Up),
+];
+
+// bindings in text input dialogs, other characters are typed into the input
+pub const PROMPT_BINDINGS: Bindings = &[
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ctrl('u')], Command::ClearModal),
+    (&[key(KeyCode::Enter)], Command::ConfirmModal),
+    (&[key(KeyCode::Backspace)], Command::BackspaceModal),
+    (&[key(KeyCode::Esc)], Command::QuitModal),
+];
+
+// bindings in searching mode, other characters are typed into the query
+pub const SEARCH_BINDINGS: Bindings = &[
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ctrl('u')], Command::ClearSearch),
+    (&[ctrl('w')], Command::EditSearch(Edit::DeleteWord)),
+    (&[key(KeyCode::Delete)], Command::EditSearch(Edit::Delete)),
+    (&[key(KeyCode::Left)], Command::EditSearch(Edit::Left)),
+    (&[ctrl('b')], Command::EditSearch(Edit::Left)),
+    (&[key(KeyCode::Right)], Command::EditSearch(Edit::Right)),
+    (&[ctrl('f')], Command::EditSearch(Edit::Right)),
+    (&[alt('b')], Command::EditSearch(Edit::WordLeft)),
+    (&[alt('f')], Command::EditSearch(Edit::WordRight)),
+    (&[key(KeyCode::Home)], Command::EditSearch(Edit::Start)),
+    (&[ctrl('a')], Command::EditSearch(Edit::Start)),
+    (&[key(KeyCode::End)], Command::EditSearch(Edit::End)),
+    (&[ctrl('e')], Command::EditSearch(Edit::End)),
+    (&[key(KeyCode::Up)], Command::PreviousHistory),
+    (&[key(KeyCode::Down)], Command::NextHistory),
+    (&[ctrl('n')], Command::Down),
+    (&[ctrl('p')], Command::Up),
+    (&[key(KeyCode::PageDown)], Command::JumpDown),
+    (&[key(KeyCode::PageUp)], Command::JumpUp),
+    (&[key(KeyCode::Enter)], Command::Searching(false)),
+    (&[key(KeyCode::Backspace)], Command::BackspaceSearch),
+    (&[key(KeyCode::Esc)], Command::QuitSearch),
+];
+
+// bindings in command mode, other characters are typed into the command line
+pub const COMMAND_BINDINGS: Bindings = &[
+    (&[ctrl('q')], Command::Quit),
+    (&[ctrl('z')], Command::Suspend),
+    (&[ctrl('u')], Command::ClearCmdline),
+    (&[key(KeyCode::Up)], Command::PreviousHistory),
+    (&[key(KeyCode::Down)], Command::NextHistory),
+    (&[key(KeyCode::PageUp)], Command::ScrollOutputUp),
+    (&[key(KeyCode::PageDown)], Command::ScrollOutputDown),
+    (&[key(KeyCode::Tab)], Command::CompleteCmdline),
+    (&[key(KeyCode::Enter)], Command::RunCmdline),
+    (&[key(KeyCode::Backspace)], Command::BackspaceCmdline),
+    (&[key(KeyCode::Esc)], Command::QuitCmdline),
+];
+
+const MODES: &[(&str, Bindings)] = &[
+    ("normal", BINDINGS),
+    ("visual", VISUAL_BINDINGS),
+    ("output", OUTPUT_BINDINGS),
+    ("search", SEARCH_BINDINGS),
+    ("command", COMMAND_BINDINGS),
+    ("help", HELP_BINDINGS),
+    ("dialog", MODAL_BINDINGS),
+    ("prompt", PROMPT_BINDINGS),
+];
+
+pub struct HelpEntry {
+    pub mode: &'static str,
+    pub keys: String,
+    pub command: String,
+    pub description: &'static str,
+}
+
+fn tables(s: &State) -> &'static [Bindings] {
+    if s.modal.is_some() {
+        &[MODAL_BINDINGS]
+    } else if s.help.is_some() {
+        &[HELP_BINDINGS]
+    } else if s.visual.is_some() && !s.output.is_empty() {
+        &[VISUAL_BINDINGS, OUTPUT_BINDINGS, BINDINGS]
+    } else if s.visual.is_some() {
+        &[VISUAL_BINDINGS, BINDINGS]
+    } else if !s.output.is_empty() {
+        &[OUTPUT_BINDINGS, BINDINGS]
+    } else {
+        &[BINDINGS]
+    }
+}
+
+// list the bindings of every mode, merging the bindings of the same command
+pub fn help() -> Vec<HelpEntry> {
+    let mut entries: Vec<HelpEntry> = Vec::new();
+    for (mode, bindings) in MODES {
+        let start = entries.len();
+        for (keys, cmd) in *bindings {
+            let keys: String = keys.iter().map(Key::to_string).collect();
+            // the name in command mode, so actions without one are only told apart by description
+            let command = ACTIONS
+                .iter()
+                .find(|(_, x)| mem::discriminant(x) == mem::discriminant(cmd))
+                .map_or_else(String::new, |(name, _)| String::from(*name));
+            let description = cmd.description();
+            if let Some(entry) = entries[start ..]
+                .iter_mut()
+                .find(|x| x.command == command && x.description == description)
+            {
+                entry.keys.push_str(", ");
+                entry.keys.push_str(&keys);
+            } else {
+                entries.push(HelpEntry {
+                    mode,
+                    keys,
+                    command,
+                    description,
+                });
+            }
+        }
+    }
+    entries
+}
+
+impl From<KeyEvent> for Key {
+    fn from(
+        KeyEvent {
+            code, modifiers, ..
+        }: KeyEvent,
+    ) -> Self {
+        Key {
+            code,
+            ctrl: modifiers.contains(KeyModifiers::CONTROL),
+            alt: modifiers.contains(KeyModifiers::ALT),
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (self.code, self.ctrl, self.alt) {
+            (KeyCode::Char(' ' | '<'), ..) => {}
+            (KeyCode::Char(c), false, false) => return write!(f, "{c}"),
+            _ => {}
+        }
+
+        f.write_str("<")?;
+        if self.ctrl {
+            f.write_str("C-")?;
+        }
+        if self.alt {
+            f.write_str("A-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char('<') => f.write_str("lt"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Backspace => f.write_str("BS"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Left => f.write_str("Left"),
+            KeyCode::Right => f.write_str("Right"),
+            KeyCode::Up => f.write_str("Up"),
+            KeyCode::Down => f.write_str("Down"),
+            KeyCode::Home => f.write_str("Home"),
+            KeyCode::End => f.write_str("End"),
+            KeyCode::PageUp => f.write_str("PageUp"),
+            KeyCode::PageDown => f.write_str("PageDown"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::BackTab => f.write_str("S-Tab"),
+            KeyCode::Delete => f.write_str("Del"),
+            KeyCode::Insert => f.write_str("Insert"),
+            KeyCode::Esc => f.write_str("Esc"),
+            _ => f.write_str("Nop"),
+        }?;
+        f.write_str(">")
+    }
+}
+
+impl Keys {
+    pub fn new(timeout: Duration) -> Self {
+        Keys {
+            timeout,
+            pending: Vec::with_capacity(4),
+            count: None,
+            deadline: None,
+        }
+    }
+
+    pub fn input(&mut self, ev: KeyEvent, s: &State) -> Option<(Command, Option<usize>)> {
+        let key = Key::from(ev);
+
+        let line = if let Some(Modal::Input { .. }) = s.modal {
+            Some((PROMPT_BINDINGS, Command::InputModal as fn(char) -> Command))
+        } else if s.modal.is_some() {
+            None
+        } else if s.commanding {
+            Some((
+                COMMAND_BINDINGS,
+                Command::InputCmdline as fn(char) -> Command,
+            ))
+        } else if s.searching || s.help.as_ref().is_some_and(|help| help.searching) {
+            Some((SEARCH_BINDINGS, Command::InputSearch as fn(char) -> Command))
+        } else {
+            None
+        };
+        if let Some((bindings, input)) = line {
+            self.reset();
+            return if let Some((_, cmd)) = bindings.iter().find(|(keys, _)| *keys == [key]) {
+                Some((cmd.clone(), None))
+            } else if let KeyCode::Char(c) = key.code {
+                Some((input(c), None))
+            } else {
+                None
+            };
+        }
+
+        if key.code == KeyCode::Esc && !self.is_empty() {
+            self.reset();
+            return None;
+        }
+
+        if let (true, KeyCode::Char(c @ '0' ..= '9'), false, false) =
+            (self.pending.is_empty(), key.code, key.ctrl, key.alt)
+        {
+            let n = c as usize - '0' as usize;
+            match self.count {
+                Some(count) => {
+                    self.count = Some(min(count * 10 + n, MAX_COUNT));
+                    return None;
+                }
+                None if n != 0 => {
+                    self.count = Some(n);
+                    return None;
+                }
+                None => {}
+            }
+        }
+
+        self.pending.push(key);
+        let mut exact = None;
+        let mut longer = false;
+        for bindings in tables(s) {
+            for (keys, cmd) in *bindings {
+                if *keys == self.pending {
+                    exact = Some(cmd);
+                } else if keys.starts_with(&self.pending) {
+                    longer = true;
+                }
+            }
+            if exact.is_some() || longer {
+                break;
+            }
+        }
+
+        match (exact, longer) {
+            (Some(cmd), false) => {
+                let count = self.count;
+                self.reset();
+                Some((cmd.clone(), count))
+            }
+            (_, true) => {
+                self.deadline = Some(Instant::now() + self.timeout);
+                None
+            }
+            (None, false) => {
+                self.reset();
+                None
+            }
+        }
+    }
+
+    // run the shorter binding if the pending keys are still ambiguous after the timeout
+    pub fn expire(&mut self, s: &State) -> Option<(Command, Option<usize>)> {
+        if !matches!(self.deadline, Some(deadline) if deadline <= Instant::now()) {
+            return None;
+        }
+
+        let cmd = tables(s)
+            .iter()
+            .flat_map(|bindings| bindings.iter())
+            .find(|(keys, _)| *keys == self.pending)
+            .map(|(_, cmd)| (cmd.clone(), self.count));
+        self.reset();
+        cmd
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn pending(&self) -> String {
+        let mut pending = self.count.map_or_else(String::new, |n| n.to_string());
+        for key in &self.pending {
+            pending.push_str(&key.to_string());
+        }
+        pending
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.count.is_none()
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.count = None;
+        self.deadline = None;
+    }
+}
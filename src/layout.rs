@@ -2,48 +2,193 @@ use anyhow::{Context, Result};
 use tui::{
     backend::Backend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Modifier, Style},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{List, ListItem, Paragraph},
+    widgets::{self, Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Frame, Terminal,
 };
 
+use std::{
+    cell::Cell,
+    cmp::{max, min},
+};
+
 use crate::{
-    app::State,
-    config::{AddStyle, Column, Condition, Constrained, Texts, Widget},
-    mpd::{PlayerState, Song, Status, Track},
+    app::{Clickable, Message, Modal, State},
+    config::{
+        AddStyle, Bar, Border, BorderType, Column, Condition, Constrained, Gauge, Screen, Sides,
+        Texts, Widget,
+    },
+    keys::HelpEntry,
+    mpd::{PlayerState, Track},
+    search::Field,
 };
 
-struct FlattenState<'a, 'b> {
-    status: &'a Status,
+struct FlattenState<'a> {
+    state: &'a State,
     current_track: Option<&'a Track>,
     queue_track: Option<&'a Track>,
     queue_current: bool,
     selected: bool,
-    searching: bool,
-    query: &'a str,
-    style: &'b Style,
+    marked: bool,
+    focused: bool,
+    screen: usize,
+    screen_name: &'a str,
+    help_entry: Option<&'a HelpEntry>,
+    message: Option<&'a Message>,
+    matched: &'a [(Field, usize)],
+    match_style: &'a [AddStyle],
+    style: Style,
+    // size of the area, the width is shared by the bars without a width
+    width: usize,
+    height: usize,
+    bar_width: usize,
+    bars: Cell<usize>,
 }
 
-struct ConditionState<'a> {
-    status: &'a Status,
-    current_track: Option<&'a Track>,
-    queue_current: bool,
-    selected: bool,
-    searching: bool,
-    query: &'a str,
+impl<'a> FlattenState<'a> {
+    fn new(state: &'a State, screens: &'a [Screen]) -> Self {
+        FlattenState {
+            state,
+            current_track: state
+                .status
+                .song
+                .as_ref()
+                .and_then(|song| state.queue.get(song.pos)),
+            queue_track: None,
+            queue_current: false,
+            selected: false,
+            marked: false,
+            focused: false,
+            screen: state.screen,
+            screen_name: &screens[state.screen].name,
+            help_entry: None,
+            message: state.message(),
+            matched: &[],
+            match_style: &[],
+            style: Style::default(),
+            width: 0,
+            height: 0,
+            bar_width: 0,
+            bars: Cell::new(0),
+        }
+    }
 }
 
-pub fn render(term: &mut Terminal<impl Backend>, widget: &Widget, s: &mut State) -> Result<()> {
+pub fn render(
+    term: &mut Terminal<impl Backend>,
+    screens: &[Screen],
+    help: &Widget,
+    s: &mut State,
+) -> Result<()> {
+    s.clickables.clear();
+    s.missing.clear();
     term.draw(|frame| {
-        _render(frame, frame.size(), widget, s);
+        let size = frame.size();
+        _render(frame, size, &screens[s.screen].layout, screens, &mut 0, s);
+
+        if s.help.is_some() {
+            let area = Rect {
+                x: size.width / 10,
+                y: size.height / 10,
+                width: size.width - size.width / 10 * 2,
+                height: size.height - size.height / 10 * 2,
+            };
+            s.clickables.clear();
+            frame.render_widget(Clear, area);
+            _render(frame, area, help, screens, &mut 0, s);
+        }
+
+        if let Some(modal) = &s.modal {
+            s.clickables.clear();
+            render_modal(frame, size, modal);
+        }
     })
     .context("Failed to draw to terminal")?;
 
     Ok(())
 }
 
-fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut State) {
+// a bordered box in the middle of the screen titled with the prompt
+fn render_modal(frame: &mut Frame<impl Backend>, size: Rect, modal: &Modal) {
+    let (prompt, lines) = match modal {
+        Modal::Confirm { prompt, .. } | Modal::Input { prompt, .. } => (prompt, 1),
+        Modal::Pick { prompt, items, .. } => (prompt, items.len()),
+    };
+    let width = min(max(size.width / 2, 40), size.width);
+    let height = min(min(lines, usize::from(size.height)) as u16 + 2, size.height);
+    let area = Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + (size.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default().borders(Borders::ALL).title(Span::styled(
+        format!(" {prompt} "),
+        Style::default()
+            .fg(Color::Indexed(113))
+            .add_modifier(Modifier::BOLD),
+    ));
+    let inner = block.inner(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    match modal {
+        Modal::Confirm { .. } => {
+            frame.render_widget(
+                Paragraph::new(Span::styled(
+                    "y: yes, n: no",
+                    Style::default().fg(Color::Indexed(185)),
+                )),
+                inner,
+            );
+        }
+        Modal::Input { input, .. } => {
+            // keep the end of the input in view
+            let len = input.chars().count() as u16 + 1;
+            frame.render_widget(
+                Paragraph::new(Span::styled(
+                    format!("{input}⎸"),
+                    Style::default().fg(Color::Indexed(185)),
+                ))
+                .scroll((0, len.saturating_sub(inner.width))),
+                inner,
+            );
+        }
+        Modal::Pick {
+            items, selected, ..
+        } => {
+            let mut liststate = ListState::default();
+            liststate.select(Some(*selected));
+            frame.render_stateful_widget(
+                List::new(
+                    items
+                        .iter()
+                        .map(|item| ListItem::new(item.as_str()))
+                        .collect::<Vec<_>>(),
+                )
+                .style(Style::default().fg(Color::Indexed(75)))
+                .highlight_style(
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::Indexed(75))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                inner,
+                &mut liststate,
+            );
+        }
+    }
+}
+
+fn _render(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    widget: &Widget,
+    screens: &[Screen],
+    pane: &mut usize,
+    s: &mut State,
+) {
     match widget {
         Widget::Rows(xs) => {
             let len = xs.capacity();
@@ -65,8 +210,13 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
                     Constrained::Min(n, w) => (w, Constraint::Min(*n)),
                     Constrained::Ratio(n, w) => (w, Constraint::Ratio(*n, denom)),
                 };
-                ws.push(w);
-                cs.push(constraint);
+                let cond = collapsible(w, size, screens, s);
+                ws.push((w, cond));
+                cs.push(if cond == Some(false) {
+                    Constraint::Length(0)
+                } else {
+                    constraint
+                });
             }
 
             let layout = Layout::default()
@@ -76,8 +226,12 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             let mut chunks = layout.split(size).into_iter();
             let mut ws = ws.into_iter();
 
-            while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                _render(frame, chunk, w, s);
+            while let (Some(chunk), Some((w, cond))) = (chunks.next(), ws.next()) {
+                if let Some(cond) = cond {
+                    render_if(frame, chunk, w, cond, screens, pane, s);
+                } else {
+                    _render(frame, chunk, w, screens, pane, s);
+                }
             }
         }
         Widget::Columns(xs) => {
@@ -100,8 +254,13 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
                     Constrained::Min(n, w) => (w, Constraint::Min(*n)),
                     Constrained::Ratio(n, w) => (w, Constraint::Ratio(*n, denom)),
                 };
-                ws.push(w);
-                cs.push(constraint);
+                let cond = collapsible(w, size, screens, s);
+                ws.push((w, cond));
+                cs.push(if cond == Some(false) {
+                    Constraint::Length(0)
+                } else {
+                    constraint
+                });
             }
 
             let layout = Layout::default()
@@ -111,24 +270,23 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             let mut chunks = layout.split(size).into_iter();
             let mut ws = ws.into_iter();
 
-            while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                _render(frame, chunk, w, s);
+            while let (Some(chunk), Some((w, cond))) = (chunks.next(), ws.next()) {
+                if let Some(cond) = cond {
+                    render_if(frame, chunk, w, cond, screens, pane, s);
+                } else {
+                    _render(frame, chunk, w, screens, pane, s);
+                }
             }
         }
         Widget::Textbox(xs) => {
             frame.render_widget(
                 Paragraph::new(flatten(
                     xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
+                    FlattenState {
+                        width: size.width.into(),
+                        height: size.height.into(),
+                        ..FlattenState::new(s, screens)
+                    },
                 )),
                 size,
             );
@@ -137,16 +295,11 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             frame.render_widget(
                 Paragraph::new(flatten(
                     xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
+                    FlattenState {
+                        width: size.width.into(),
+                        height: size.height.into(),
+                        ..FlattenState::new(s, screens)
+                    },
                 ))
                 .alignment(Alignment::Center),
                 size,
@@ -156,16 +309,11 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             frame.render_widget(
                 Paragraph::new(flatten(
                     xs,
-                    &s.status,
-                    s.status
-                        .song
-                        .as_ref()
-                        .and_then(|song| s.queue.get(song.pos)),
-                    None,
-                    false,
-                    false,
-                    s.searching,
-                    &s.query,
+                    FlattenState {
+                        width: size.width.into(),
+                        height: size.height.into(),
+                        ..FlattenState::new(s, screens)
+                    },
                 ))
                 .alignment(Alignment::Right),
                 size,
@@ -176,11 +324,26 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
             let mut ws = Vec::with_capacity(len);
             let mut cs = Vec::with_capacity(len);
 
+            let id = *pane;
+            *pane += 1;
             let len = s.queue.len();
             if len == 0 {
                 return;
             }
 
+            let focused = id == s.focus();
+            let (selected, offset) = if let Some(pane) = s.screen_states[s.screen].panes.get(id) {
+                (pane.selected, pane.offset)
+            } else {
+                return;
+            };
+
+            // only the visible rows are flattened, scrolled the same way tui would
+            let len = s.len();
+            let height = size.height as usize;
+            let offset = list_offset(offset, selected, len, size.height);
+            let rows = offset .. min(offset + height, len);
+
             let denom = xs.iter().fold(0, |n, Column { item, .. }| {
                 if let Constrained::Ratio(m, _) = item {
                     n + m
@@ -189,11 +352,7 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
                 }
             });
 
-            let (pos, current_track) = if let Some(Song { pos, .. }) = s.status.song {
-                (Some(pos), s.queue.get(pos))
-            } else {
-                (None, None)
-            };
+            let pos = s.status.song.as_ref().map(|song| song.pos);
 
             for column in xs {
                 let (txts, constraint) = match &column.item {
@@ -203,31 +362,52 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
                     Constrained::Ratio(n, txts) => (txts, Constraint::Ratio(*n, denom)),
                 };
 
-                let mut items = Vec::with_capacity(len);
-                if s.query.is_empty() {
-                    for (i, track) in s.queue.iter().enumerate() {
+                let mut items = Vec::with_capacity(rows.len());
+                if !s.filtering() {
+                    for i in rows.clone() {
+                        let track = s.queue.get(i);
                         items.push(ListItem::new(flatten(
                             txts,
-                            &s.status,
-                            current_track,
-                            Some(track),
-                            pos == Some(i),
-                            s.liststate.selected() == Some(i),
-                            s.searching,
-                            &s.query,
+                            FlattenState {
+                                queue_track: track,
+                                matched: s.found(i),
+                                match_style: &column.match_style,
+                                queue_current: pos == Some(i),
+                                selected: selected == i,
+                                marked: track.is_some_and(|track| {
+                                    if focused {
+                                        s.is_marked(i, track.id)
+                                    } else {
+                                        s.marked.contains(&track.id)
+                                    }
+                                }),
+                                focused,
+                                ..FlattenState::new(s, screens)
+                            },
                         )));
                     }
                 } else {
-                    for &i in &s.filtered {
+                    for (i, &x) in s.filtered[rows.clone()].iter().enumerate() {
+                        let i = offset + i;
+                        let track = s.queue.get(x);
                         items.push(ListItem::new(flatten(
                             txts,
-                            &s.status,
-                            current_track,
-                            s.queue.get(i),
-                            pos == Some(i),
-                            s.liststate.selected() == Some(i),
-                            s.searching,
-                            &s.query,
+                            FlattenState {
+                                queue_track: track,
+                                matched: &s.matched[i],
+                                match_style: &column.match_style,
+                                queue_current: pos == Some(x),
+                                selected: selected == i,
+                                marked: track.is_some_and(|track| {
+                                    if focused {
+                                        s.is_marked(i, track.id)
+                                    } else {
+                                        s.marked.contains(&track.id)
+                                    }
+                                }),
+                                focused,
+                                ..FlattenState::new(s, screens)
+                            },
                         )));
                     }
                 }
@@ -243,56 +423,396 @@ fn _render(frame: &mut Frame<impl Backend>, size: Rect, widget: &Widget, s: &mut
                 .direction(Direction::Horizontal)
                 .constraints(cs);
 
-            let mut chunks = layout.split(size).into_iter();
-            let mut ws = ws.into_iter();
+            let mut liststate = ListState::default();
+            if rows.contains(&selected) {
+                liststate.select(Some(selected - offset));
+            }
+            for (chunk, w) in layout.split(size).into_iter().zip(ws) {
+                frame.render_stateful_widget(w, chunk, &mut liststate.clone());
+            }
+
+            s.screen_states[s.screen].panes[id].offset = offset;
+            for i in rows.clone() {
+                if let Some(x) = s.queue_pos(i).filter(|&x| s.queue.get(x).is_none()) {
+                    s.missing.push(x);
+                }
+            }
+            for i in rows {
+                s.clickables.push((
+                    Rect {
+                        y: size.y + (i - offset) as u16,
+                        height: 1,
+                        ..size
+                    },
+                    Clickable::Row(id, i),
+                ));
+            }
+        }
+        Widget::Output => {
+            frame.render_widget(
+                Paragraph::new(
+                    s.output
+                        .iter()
+                        .skip(s.output_offset)
+                        .take(size.height as usize)
+                        .map(|line| Spans::from(line.as_str()))
+                        .collect::<Vec<_>>(),
+                ),
+                size,
+            );
+        }
+        Widget::Tabs(xs) => {
+            let mut spans = Vec::new();
+            let mut tabs = Vec::with_capacity(screens.len());
+            let mut x = size.x;
+            for (i, screen) in screens.iter().enumerate() {
+                let start = spans.len();
+                _flatten(
+                    &mut spans,
+                    xs,
+                    &FlattenState {
+                        screen: i,
+                        screen_name: &screen.name,
+                        ..FlattenState::new(s, screens)
+                    },
+                );
+                let width = spans[start ..].iter().map(Span::width).sum::<usize>();
+                let end = min(x as usize + width, size.right() as usize) as u16;
+                if end > x {
+                    tabs.push((
+                        Rect {
+                            x,
+                            width: end - x,
+                            ..size
+                        },
+                        Clickable::Screen(i),
+                    ));
+                }
+                x = end;
+            }
+            frame.render_widget(Paragraph::new(Spans::from(spans)), size);
+            s.clickables.extend(tabs);
+        }
+        Widget::Help(xs) => {
+            let len = xs.capacity();
+            let mut ws = Vec::with_capacity(len);
+            let mut cs = Vec::with_capacity(len);
+
+            let entries: Vec<_> = if let Some(help) = &s.help {
+                help.filtered.clone()
+            } else {
+                (0 .. s.help_entries.len()).collect()
+            };
+            let height = size.height as usize;
+            let offset = min(
+                s.help.as_ref().map_or(0, |help| help.offset),
+                entries.len().saturating_sub(height),
+            );
 
-            if let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                frame.render_stateful_widget(w, chunk, &mut s.liststate);
-                while let (Some(chunk), Some(w)) = (chunks.next(), ws.next()) {
-                    frame.render_stateful_widget(w, chunk, &mut s.liststate.clone());
+            let denom = xs.iter().fold(0, |n, Column { item, .. }| {
+                if let Constrained::Ratio(m, _) = item {
+                    n + m
+                } else {
+                    n
                 }
+            });
+
+            for column in xs {
+                let (txts, constraint) = match &column.item {
+                    Constrained::Fixed(n, txts) => (txts, Constraint::Length(*n)),
+                    Constrained::Max(n, txts) => (txts, Constraint::Max(*n)),
+                    Constrained::Min(n, txts) => (txts, Constraint::Min(*n)),
+                    Constrained::Ratio(n, txts) => (txts, Constraint::Ratio(*n, denom)),
+                };
+
+                let items: Vec<_> = entries
+                    .iter()
+                    .skip(offset)
+                    .take(height)
+                    .map(|&i| {
+                        ListItem::new(flatten(
+                            txts,
+                            FlattenState {
+                                help_entry: Some(&s.help_entries[i]),
+                                ..FlattenState::new(s, screens)
+                            },
+                        ))
+                    })
+                    .collect();
+                ws.push(List::new(items).style(patch_style(Style::default(), &column.style)));
+                cs.push(constraint);
+            }
+
+            let layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(cs);
+
+            for (chunk, w) in layout.split(size).into_iter().zip(ws) {
+                frame.render_widget(w, chunk);
+            }
+
+            if let Some(help) = &mut s.help {
+                help.offset = offset;
             }
         }
+        Widget::Log(xs) => {
+            let height = size.height as usize;
+            let skip = s.messages.len().saturating_sub(height);
+            frame.render_widget(
+                Paragraph::new(
+                    s.messages[skip ..]
+                        .iter()
+                        .map(|message| {
+                            flatten(
+                                xs,
+                                FlattenState {
+                                    message: Some(message),
+                                    ..FlattenState::new(s, screens)
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                size,
+            );
+        }
+        Widget::Button(cmd, w) => {
+            s.clickables.push((size, Clickable::Button(cmd.clone())));
+            _render(frame, size, w, screens, pane, s);
+        }
+        Widget::Seek(w) => {
+            s.clickables.push((size, Clickable::Gauge(Gauge::Elapsed)));
+            _render(frame, size, w, screens, pane, s);
+        }
+        Widget::Progress(bar) => render_gauge(frame, size, Gauge::Elapsed, bar, s),
+        Widget::Gauge(gauge, bar) => render_gauge(frame, size, *gauge, bar, s),
+        Widget::If(cond, ..) => {
+            let cond = eval_cond(
+                cond,
+                &FlattenState {
+                    width: size.width.into(),
+                    height: size.height.into(),
+                    ..FlattenState::new(s, screens)
+                },
+            );
+
+            render_if(frame, size, widget, cond, screens, pane, s);
+        }
+        Widget::Block {
+            borders,
+            border_type,
+            title,
+            style,
+            border_style,
+            margin,
+            padding,
+            inner,
+        } => {
+            let size = shrink(size, margin);
+            let borders = borders.iter().fold(Borders::NONE, |acc, border| {
+                acc | match border {
+                    Border::All => Borders::ALL,
+                    Border::Top => Borders::TOP,
+                    Border::Bottom => Borders::BOTTOM,
+                    Border::Left => Borders::LEFT,
+                    Border::Right => Borders::RIGHT,
+                }
+            });
+            let block = Block::default()
+                .borders(borders)
+                .border_type(match border_type {
+                    BorderType::Plain => widgets::BorderType::Plain,
+                    BorderType::Rounded => widgets::BorderType::Rounded,
+                    BorderType::Double => widgets::BorderType::Double,
+                    BorderType::Thick => widgets::BorderType::Thick,
+                })
+                .border_style(patch_style(Style::default(), border_style))
+                .style(patch_style(Style::default(), style));
+            let inner_size = shrink(block.inner(size), padding);
+            frame.render_widget(
+                block.title(flatten(
+                    title,
+                    FlattenState {
+                        width: inner_size.width.into(),
+                        height: 1,
+                        ..FlattenState::new(s, screens)
+                    },
+                )),
+                size,
+            );
+            _render(frame, inner_size, inner, screens, pane, s);
+        }
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn flatten<'a>(
-    xs: &'a Texts,
-    status: &'a Status,
-    current_track: Option<&'a Track>,
-    queue_track: Option<&'a Track>,
-    queue_current: bool,
-    selected: bool,
-    searching: bool,
-    query: &'a str,
-) -> Spans<'a> {
+fn shrink(size: Rect, sides: &Sides) -> Rect {
+    let x = min(size.x + sides.left, size.right());
+    let y = min(size.y + sides.top, size.bottom());
+    Rect {
+        x,
+        y,
+        width: size.right().saturating_sub(sides.right).saturating_sub(x),
+        height: size.bottom().saturating_sub(sides.bottom).saturating_sub(y),
+    }
+}
+
+// fill the area according to the value of the gauge, with the head right after the filled part
+fn render_gauge(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    gauge: Gauge,
+    bar: &Bar,
+    s: &mut State,
+) {
+    let (filled, head, empty) = bar_cells(gauge_ratio(gauge, s), size.width.into(), &bar.head);
+    let line = Spans::from(vec![
+        Span::styled(
+            bar.fill.repeat(filled),
+            patch_style(Style::default(), &bar.fill_style),
+        ),
+        Span::styled(head, patch_style(Style::default(), &bar.head_style)),
+        Span::styled(
+            bar.empty.repeat(empty),
+            patch_style(Style::default(), &bar.empty_style),
+        ),
+    ]);
+
+    frame.render_widget(Paragraph::new(vec![line; usize::from(size.height)]), size);
+    s.clickables.push((size, Clickable::Gauge(gauge)));
+}
+
+fn gauge_ratio(gauge: Gauge, s: &State) -> f64 {
+    match gauge {
+        Gauge::Elapsed => s.status.song.as_ref().map_or(0.0, |song| {
+            s.queue.get(song.pos).map_or(0.0, |track| {
+                f64::from(s.elapsed().unwrap_or_default()) / f64::from(max(track.time, 1))
+            })
+        }),
+        Gauge::Volume => s
+            .status
+            .volume
+            .map_or(0.0, |volume| f64::from(volume) / 100.0),
+        Gauge::QueuePosition => s.selected() as f64 / max(s.len(), 2).saturating_sub(1) as f64,
+    }
+}
+
+// the number of filled and empty cells, with the head right after the filled part if it fits
+fn bar_cells(ratio: f64, width: usize, head: &str) -> (usize, &str, usize) {
+    let filled = min((ratio * width as f64) as usize, width);
+    let head = if filled < width { head } else { "" };
+    (filled, head, width - filled - usize::from(!head.is_empty()))
+}
+
+// the number of widgets with their own selection and scroll state
+pub fn panes(widget: &Widget) -> usize {
+    match widget {
+        Widget::Rows(xs) | Widget::Columns(xs) => xs
+            .iter()
+            .map(|x| match x {
+                Constrained::Max(_, w)
+                | Constrained::Min(_, w)
+                | Constrained::Fixed(_, w)
+                | Constrained::Ratio(_, w) => panes(w),
+            })
+            .sum(),
+        Widget::Queue(_) => 1,
+        Widget::Button(_, w) | Widget::Seek(w) => panes(w),
+        Widget::If(_, lhs, rhs) => panes(lhs) + rhs.as_deref().map_or(0, panes),
+        Widget::Block { inner, .. } => panes(inner),
+        Widget::Textbox(_)
+        | Widget::TextboxC(_)
+        | Widget::TextboxR(_)
+        | Widget::Output
+        | Widget::Tabs(_)
+        | Widget::Help(_)
+        | Widget::Log(_)
+        | Widget::Progress(_)
+        | Widget::Gauge(..) => 0,
+    }
+}
+
+// render either branch of an If
+fn render_if(
+    frame: &mut Frame<impl Backend>,
+    size: Rect,
+    w: &Widget,
+    cond: bool,
+    screens: &[Screen],
+    pane: &mut usize,
+    s: &mut State,
+) {
+    let Widget::If(_, lhs, rhs) = w else {
+        return;
+    };
+
+    // skip the panes of the other branch so every pane keeps its state
+    let rhs_panes = rhs.as_deref().map_or(0, panes);
+    if cond {
+        _render(frame, size, lhs, screens, pane, s);
+        *pane += rhs_panes;
+    } else {
+        *pane += panes(lhs);
+        if let Some(rhs) = rhs {
+            _render(frame, size, rhs, screens, pane, s);
+        }
+    }
+}
+
+// an If without an else takes no space in rows and columns while its condition is false, so its
+// condition is evaluated once against the area of the rows or columns instead of its own
+fn collapsible(w: &Widget, size: Rect, screens: &[Screen], s: &State) -> Option<bool> {
+    match w {
+        Widget::If(cond, _, None) => Some(eval_cond(
+            cond,
+            &FlattenState {
+                width: size.width.into(),
+                height: size.height.into(),
+                ..FlattenState::new(s, screens)
+            },
+        )),
+        _ => None,
+    }
+}
+
+// the offset to scroll a list of single line items to, given the previous offset
+fn list_offset(offset: usize, selected: usize, len: usize, height: u16) -> usize {
+    let height = height as usize;
+    let selected = min(selected, len.saturating_sub(1));
+    let offset = min(offset, len.saturating_sub(1));
+    if selected >= offset + height {
+        // an empty list or area still scrolls no further than its end
+        min((selected + 1).saturating_sub(height), len)
+    } else {
+        min(offset, selected)
+    }
+}
+
+fn flatten<'a>(xs: &'a Texts, s: FlattenState<'a>) -> Spans<'a> {
     let mut spans = Vec::new();
-    _flatten(
-        &mut spans,
-        xs,
-        &FlattenState {
-            status,
-            current_track,
-            queue_track,
-            queue_current,
-            selected,
-            searching,
-            query,
-            style: &Style::default(),
-        },
-    );
+    _flatten(&mut spans, xs, &s);
+
+    // flatten again once the width left by the other texts is known
+    let used: usize = spans.iter().map(Span::width).sum();
+    if let Some(bar_width) = s.width.saturating_sub(used).checked_div(s.bars.get()) {
+        spans.clear();
+        _flatten(&mut spans, xs, &FlattenState { bar_width, ..s });
+    }
+
     spans.into()
 }
 
-fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '_>) {
+fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a>) {
     match xs {
-        Texts::Text(x) => spans.push(Span::styled(x, *s.style)),
+        Texts::Text(x) => spans.push(Span::styled(x, s.style)),
         Texts::CurrentElapsed => {
-            if let Some(Song { elapsed, .. }) = s.status.song {
+            if let Some(elapsed) = s.state.elapsed() {
+                // stop at the end of the song until the next status arrives
+                let elapsed = s
+                    .current_track
+                    .map_or(elapsed as u16, |track| min(elapsed as u16, track.time));
                 spans.push(Span::styled(
                     format!("{}:{:02}", elapsed / 60, elapsed % 60),
-                    *s.style,
+                    s.style,
                 ));
             }
         }
@@ -300,13 +820,13 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
             if let Some(Track { time, .. }) = s.current_track {
                 spans.push(Span::styled(
                     format!("{}:{:02}", time / 60, time % 60),
-                    *s.style,
+                    s.style,
                 ));
             }
         }
         Texts::CurrentFile => {
             if let Some(Track { file, .. }) = s.current_track {
-                spans.push(Span::styled(file, *s.style));
+                spans.push(Span::styled(file, s.style));
             }
         }
         Texts::CurrentTitle => {
@@ -314,7 +834,7 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 title: Some(title), ..
             }) = s.current_track
             {
-                spans.push(Span::styled(title, *s.style));
+                spans.push(Span::styled(title, s.style));
             }
         }
         Texts::CurrentArtist => {
@@ -323,7 +843,7 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 ..
             }) = s.current_track
             {
-                spans.push(Span::styled(artist, *s.style));
+                spans.push(Span::styled(artist, s.style));
             }
         }
         Texts::CurrentAlbum => {
@@ -331,20 +851,20 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 album: Some(album), ..
             }) = s.current_track
             {
-                spans.push(Span::styled(album, *s.style));
+                spans.push(Span::styled(album, s.style));
             }
         }
         Texts::QueueDuration => {
             if let Some(Track { time, .. }) = s.queue_track {
                 spans.push(Span::styled(
                     format!("{}:{:02}", time / 60, time % 60),
-                    *s.style,
+                    s.style,
                 ));
             }
         }
         Texts::QueueFile => {
-            if let Some(Track { file, .. }) = s.current_track {
-                spans.push(Span::styled(file, *s.style));
+            if let Some(Track { file, .. }) = s.queue_track {
+                push_matched(spans, file, Field::File, s);
             }
         }
         Texts::QueueTitle => {
@@ -352,7 +872,7 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 title: Some(title), ..
             }) = s.queue_track
             {
-                spans.push(Span::styled(title, *s.style));
+                push_matched(spans, title, Field::Title, s);
             }
         }
         Texts::QueueArtist => {
@@ -361,7 +881,7 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 ..
             }) = s.queue_track
             {
-                spans.push(Span::styled(artist, *s.style));
+                push_matched(spans, artist, Field::Artist, s);
             }
         }
         Texts::QueueAlbum => {
@@ -369,21 +889,99 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
                 album: Some(album), ..
             }) = s.queue_track
             {
-                spans.push(Span::styled(album, *s.style));
+                push_matched(spans, album, Field::Album, s);
             }
         }
         Texts::Query => {
-            spans.push(Span::styled(String::from(s.query), *s.style));
+            if s.state.searching {
+                // draw the cursor as a reversed character, or a space at the end
+                let (before, cursor, after) = s.state.query.split();
+                spans.push(Span::styled(before, s.style));
+                spans.push(Span::styled(
+                    if cursor.is_empty() { " " } else { cursor },
+                    s.style.add_modifier(Modifier::REVERSED),
+                ));
+                spans.push(Span::styled(after, s.style));
+            } else {
+                spans.push(Span::styled(s.state.query.as_str(), s.style));
+            }
         }
-        Texts::Styled(styles, xs) => {
-            _flatten(
-                spans,
-                xs,
-                &FlattenState {
-                    style: &patch_style(*s.style, styles),
-                    ..*s
+        Texts::PendingKeys => {
+            spans.push(Span::styled(&s.state.pending, s.style));
+        }
+        Texts::CommandLine => {
+            spans.push(Span::styled(&s.state.cmdline, s.style));
+        }
+        Texts::MatchIndex => {
+            if !s.state.query.is_empty() {
+                spans.push(Span::styled(s.state.match_index().to_string(), s.style));
+            }
+        }
+        Texts::MatchCount => {
+            if !s.state.query.is_empty() {
+                spans.push(Span::styled(s.state.filtered.len().to_string(), s.style));
+            }
+        }
+        Texts::QueryError => {
+            if let Some(e) = s.state.query_error() {
+                spans.push(Span::styled(e, s.style));
+            }
+        }
+        Texts::ScreenName => {
+            spans.push(Span::styled(s.screen_name, s.style));
+        }
+        Texts::HelpMode => {
+            if let Some(HelpEntry { mode, .. }) = s.help_entry {
+                spans.push(Span::styled(*mode, s.style));
+            }
+        }
+        Texts::HelpKeys => {
+            if let Some(HelpEntry { keys, .. }) = s.help_entry {
+                spans.push(Span::styled(keys, s.style));
+            }
+        }
+        Texts::HelpCommand => {
+            if let Some(HelpEntry { command, .. }) = s.help_entry {
+                spans.push(Span::styled(command, s.style));
+            }
+        }
+        Texts::HelpDescription => {
+            if let Some(HelpEntry { description, .. }) = s.help_entry {
+                spans.push(Span::styled(*description, s.style));
+            }
+        }
+        Texts::Message => {
+            if let Some(Message { text, .. }) = s.message {
+                spans.push(Span::styled(text, s.style));
+            }
+        }
+        Texts::Bar(bar) => {
+            let width = bar.width.map_or_else(
+                || {
+                    s.bars.set(s.bars.get() + 1);
+                    s.bar_width
                 },
+                usize::from,
             );
+            let (filled, head, empty) =
+                bar_cells(gauge_ratio(bar.gauge, s.state), width, &bar.head);
+            spans.push(Span::styled(bar.fill.repeat(filled), s.style));
+            spans.push(Span::styled(head, s.style));
+            spans.push(Span::styled(bar.empty.repeat(empty), s.style));
+        }
+        Texts::HelpQuery => {
+            if let Some(help) = &s.state.help {
+                spans.push(Span::styled(&help.query, s.style));
+            }
+        }
+        Texts::Styled(styles, xs) => {
+            let styled = FlattenState {
+                style: patch_style(s.style, styles),
+                bars: Cell::new(0),
+                ..*s
+            };
+            _flatten(spans, xs, &styled);
+            s.bars.set(s.bars.get() + styled.bars.get());
         }
         Texts::Parts(xss) => {
             for xs in xss {
@@ -391,44 +989,44 @@ fn _flatten<'a>(spans: &mut Vec<Span<'a>>, xs: &'a Texts, s: &FlattenState<'a, '
             }
         }
         Texts::If(cond, xs, Some(ys)) => {
-            _flatten(
-                spans,
-                if eval_cond(
-                    cond,
-                    &ConditionState {
-                        status: s.status,
-                        current_track: s.current_track,
-                        queue_current: s.queue_current,
-                        selected: s.selected,
-                        searching: s.searching,
-                        query: s.query,
-                    },
-                ) {
-                    xs
-                } else {
-                    ys
-                },
-                s,
-            );
+            _flatten(spans, if eval_cond(cond, s) { xs } else { ys }, s);
         }
         Texts::If(cond, xs, None) => {
-            if eval_cond(
-                cond,
-                &ConditionState {
-                    status: s.status,
-                    current_track: s.current_track,
-                    queue_current: s.queue_current,
-                    selected: s.selected,
-                    searching: s.searching,
-                    query: s.query,
-                },
-            ) {
+            if eval_cond(cond, s) {
                 _flatten(spans, xs, s);
             }
         }
     }
 }
 
+// highlight the characters of the field matched by the query
+fn push_matched<'a>(spans: &mut Vec<Span<'a>>, x: &'a str, field: Field, s: &FlattenState<'a>) {
+    if s.match_style.is_empty() || !s.matched.iter().any(|(f, _)| *f == field) {
+        spans.push(Span::styled(x, s.style));
+        return;
+    }
+
+    let highlight = patch_style(s.style, s.match_style);
+    let mut start = 0;
+    let mut matched = false;
+    for (i, (j, _)) in x.char_indices().enumerate() {
+        if s.matched.contains(&(field, i)) != matched {
+            if j > start {
+                spans.push(Span::styled(
+                    &x[start .. j],
+                    if matched { highlight } else { s.style },
+                ));
+            }
+            start = j;
+            matched = !matched;
+        }
+    }
+    spans.push(Span::styled(
+        &x[start ..],
+        if matched { highlight } else { s.style },
+    ));
+}
+
 fn patch_style(style: Style, styles: &[AddStyle]) -> Style {
     let mut style = style;
     for add_style in styles {
@@ -498,16 +1096,16 @@ fn patch_style(style: Style, styles: &[AddStyle]) -> Style {
     style
 }
 
-fn eval_cond(cond: &Condition, s: &ConditionState) -> bool {
+fn eval_cond(cond: &Condition, s: &FlattenState) -> bool {
     match cond {
-        Condition::Repeat => s.status.repeat,
-        Condition::Random => s.status.random,
-        Condition::Single => s.status.single == Some(true),
-        Condition::Oneshot => s.status.single.is_none(),
-        Condition::Consume => s.status.consume,
-        Condition::Playing => s.status.state == PlayerState::Play,
-        Condition::Paused => s.status.state == PlayerState::Pause,
-        Condition::Stopped => s.status.state == PlayerState::Stop,
+        Condition::Repeat => s.state.status.repeat,
+        Condition::Random => s.state.status.random,
+        Condition::Single => s.state.status.single == Some(true),
+        Condition::Oneshot => s.state.status.single.is_none(),
+        Condition::Consume => s.state.status.consume,
+        Condition::Playing => s.state.status.state == PlayerState::Play,
+        Condition::Paused => s.state.status.state == PlayerState::Pause,
+        Condition::Stopped => s.state.status.state == PlayerState::Stop,
         Condition::TitleExist => matches!(s.current_track, Some(Track { title: Some(_), .. })),
         Condition::ArtistExist => matches!(
             s.current_track,
@@ -519,8 +1117,21 @@ fn eval_cond(cond: &Condition, s: &ConditionState) -> bool {
         Condition::AlbumExist => matches!(s.current_track, Some(Track { album: Some(_), .. })),
         Condition::QueueCurrent => s.queue_current,
         Condition::Selected => s.selected,
-        Condition::Searching => s.searching,
-        Condition::Filtered => !s.query.is_empty(),
+        Condition::Searching => s.state.searching,
+        Condition::Filtered => s.state.filtering(),
+        Condition::Finding => s.state.finding,
+        Condition::Commanding => s.state.commanding,
+        Condition::OutputExist => !s.state.output.is_empty(),
+        Condition::Marked => s.marked,
+        Condition::Visual => s.state.visual.is_some(),
+        Condition::ScreenCurrent => s.screen == s.state.screen,
+        Condition::Focused => s.focused,
+        Condition::QueryErrorExist => s.state.query_error().is_some(),
+        Condition::MessageExist => s.message.is_some(),
+        Condition::MessageError => matches!(s.message, Some(Message { error: true, .. })),
+        Condition::HelpSearching => s.state.help.as_ref().is_some_and(|help| help.searching),
+        Condition::WidthAtLeast(n) => s.width >= usize::from(*n),
+        Condition::HeightAtLeast(n) => s.height >= usize::from(*n),
         Condition::Not(x) => !eval_cond(x, s),
         Condition::And(x, y) => eval_cond(x, s) && eval_cond(y, s),
         Condition::Or(x, y) => eval_cond(x, s) || eval_cond(y, s),
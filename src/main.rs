@@ -2,49 +2,89 @@
 
 mod app;
 mod cli;
+mod cmdline;
 mod config;
 mod defaults;
+mod events;
 mod fail;
+mod history;
+mod input;
+mod keys;
 mod layout;
 mod mpd;
+mod search;
 
 use anyhow::{Context, Result};
 use async_io::{block_on, Timer};
 use async_net::resolve;
 use clap::Parser;
-use crossbeam_queue::SegQueue;
 use crossterm::{
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
-        MouseEvent, MouseEventKind,
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        MouseButton, MouseEvent, MouseEventKind,
     },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use dirs::config_dir;
-use futures_lite::StreamExt;
-use tui::{backend::CrosstermBackend, widgets::ListState, Terminal};
+use futures_lite::{future, FutureExt, StreamExt};
+#[cfg(unix)]
+use rustix::process::{self, Signal};
+#[cfg(unix)]
+use signal_hook::consts::{SIGCONT, SIGTSTP};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::Rect,
+    Terminal,
+};
 
 use std::{
-    cmp::min,
+    cmp::{max, min},
+    collections::HashSet,
     env, fs,
     io::stdout,
+    mem, panic,
     process::exit,
-    sync::{
-        atomic::{AtomicU8, Ordering},
-        Arc,
-    },
-    thread::{self, Thread},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    app::{Command, State},
+    app::{Clickable, Command, Help, Modal, Pane, ScreenState, State},
     cli::Opts,
+    cmdline::Completer,
+    config::{Gauge, Screen, SearchFolding, Widget},
+    events::Event,
+    history::History,
+    input::LineInput,
+    keys::Keys,
     layout::render,
-    mpd::{Client, PlayerState},
+    mpd::{Client, PlayerState, Queue},
 };
 
+// how often the status is fetched while playing to correct the elapsed time counted locally
+const RESYNC: Duration = Duration::from_secs(10);
+
+// what to update after handling an event, combined with |
+const TICK: u8 = 0b1000;
+const STATUS: u8 = 0b0100;
+const QUEUE: u8 = 0b0010;
+const FRAME: u8 = 0b0001;
+
+fn setup() -> Result<()> {
+    enable_raw_mode().context("Failed to enable raw mode")?;
+    let mut stdout = stdout();
+    stdout
+        .execute(EnableMouseCapture)
+        .context("Failed to enable mouse capture")?;
+    stdout
+        .execute(EnableBracketedPaste)
+        .context("Failed to enable bracketed paste")?;
+    stdout
+        .execute(EnterAlternateScreen)
+        .context("Failed to enter alternate screen")?;
+    Ok(())
+}
+
 fn cleanup() -> Result<()> {
     let mut stdout = stdout();
     stdout
@@ -53,22 +93,81 @@ fn cleanup() -> Result<()> {
     stdout
         .execute(DisableMouseCapture)
         .context("Failed to disable mouse capture")?;
+    stdout
+        .execute(DisableBracketedPaste)
+        .context("Failed to disable bracketed paste")?;
     disable_raw_mode().context("Failed to disable raw mode")?;
     Ok(())
 }
 
+// the command for clicking or dragging to a column of a gauge
+fn gauge_command(area: Rect, gauge: Gauge, column: u16, s: &State) -> Command {
+    let x = min(column.saturating_sub(area.x), area.width - 1);
+    let last = max(area.width - 1, 1);
+    match gauge {
+        Gauge::Elapsed => Command::SeekTo(f32::from(x) / f32::from(area.width)),
+        Gauge::Volume => Command::SetVolume((u32::from(x) * 100 / u32::from(last)) as u8),
+        Gauge::QueuePosition => {
+            Command::Select(usize::from(x) * s.len().saturating_sub(1) / usize::from(last))
+        }
+    }
+}
+
+// draw the frame, then again once the songs drawn before they were loaded are loaded
+async fn draw(
+    term: &mut Terminal<impl Backend>,
+    screens: &[Screen],
+    help: &Widget,
+    s: &mut State,
+    cl: &mut Client,
+) -> Result<()> {
+    if let Some(song) = &s.status.song {
+        cl.load_queue(&mut s.queue, [song.pos]).await?;
+    }
+    render(term, screens, help, s)?;
+    if !s.missing.is_empty() {
+        cl.load_queue(&mut s.queue, mem::take(&mut s.missing))
+            .await?;
+        render(term, screens, help, s)?;
+    }
+    Ok(())
+}
+
+// the songs a batch action applies to, loading the marked songs first since they can be out of view
+async fn targets(s: &mut State, cl: &mut Client) -> Result<Vec<usize>> {
+    cl.load_ids(&mut s.queue, s.marked.iter().copied()).await?;
+    Ok(s.targets())
+}
+
 fn main() {
-    let res = block_on(run());
-    if let Err(e) = cleanup().and(res) {
-        eprintln!("{e:?}");
-        exit(1);
+    // restore the terminal before the message is printed, since panics abort in release builds
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = cleanup();
+        hook(info);
+    }));
+
+    let res = block_on(async {
+        let terminated = events::terminated()?;
+        async { run().await.map(|_| None) }
+            .or(async { terminated.await.map(Some) })
+            .await
+    });
+    match cleanup().and(res) {
+        Ok(None) => {}
+        // the status shells report for processes killed by the signal
+        Ok(Some(sig)) => exit(128 + sig),
+        Err(e) => {
+            eprintln!("{e:?}");
+            exit(1);
+        }
     }
 }
 
 async fn run() -> Result<()> {
     let opts = Opts::parse();
 
-    let cfg = if let Some(file) = opts.config {
+    let mut cfg = if let Some(file) = opts.config {
         ron::de::from_bytes(&fs::read(&file).with_context(fail::read(file.display()))?)
             .with_context(fail::parse_cfg(file.display()))?
     } else if let Some(xs) = config_dir() {
@@ -94,6 +193,15 @@ async fn run() -> Result<()> {
         resolve(cfg.address).await?
     };
 
+    let screens = if cfg.screens.is_empty() {
+        vec![Screen {
+            name: String::from("Queue"),
+            layout: cfg.layout,
+        }]
+    } else {
+        cfg.screens
+    };
+
     let mut cl = Client::init(addr).await?;
     if let Some(cmd) = opts.cmd {
         for cmd in cmd {
@@ -102,32 +210,74 @@ async fn run() -> Result<()> {
         exit(0); // skip cleanup
     }
 
+    let lazy_queue = opts.lazy_queue
+        || if opts.no_lazy_queue {
+            false
+        } else {
+            cfg.lazy_queue
+        };
+    if lazy_queue {
+        // mpd compares the songs as they are apart from case
+        cfg.search_folding = SearchFolding {
+            accents: false,
+            width: false,
+            kana: false,
+        };
+    }
+
     let status = cl.status().await?;
     let mut idle_cl = Client::init(addr).await?;
-    let (queue, mut queue_strings) = idle_cl.queue(status.queue_len, &cfg.search_fields).await?;
+    let (queue, mut queue_strings) = if lazy_queue {
+        (Queue::new(status.queue_len), Vec::new())
+    } else {
+        idle_cl
+            .queue(status.queue_len, &cfg.search_fields, &cfg.search_folding)
+            .await?
+    };
     let mut s = State {
-        selected: 0,
         status,
+        status_time: Instant::now(),
         queue,
-        liststate: ListState::default(),
+        missing: Vec::new(),
         searching: false,
-        query: String::with_capacity(32),
+        query: LineInput::default(),
+        finding: false,
         filtered: Vec::new(),
+        matched: Vec::new(),
+        query_error: None,
+        pending: String::new(),
+        commanding: false,
+        cmdline: String::with_capacity(32),
+        output: Vec::new(),
+        output_offset: 0,
+        marked: HashSet::new(),
+        visual: None,
+        clickables: Vec::new(),
+        screen: 0,
+        screen_states: screens
+            .iter()
+            .map(|screen| ScreenState {
+                panes: vec![Pane::default(); max(layout::panes(&screen.layout), 1)],
+                focus: 0,
+            })
+            .collect(),
+        help: None,
+        help_entries: keys::help(),
+        modal: None,
+        messages: Vec::new(),
+        message_deadline: None,
     };
-    s.reselect();
+    s.reselect_all();
 
-    enable_raw_mode().context("Failed to enable raw mode")?;
-    let mut stdout = stdout();
-    stdout
-        .execute(EnableMouseCapture)
-        .context("Failed to enable mouse capture")?;
-    stdout
-        .execute(EnterAlternateScreen)
-        .context("Failed to enter alternate screen")?;
+    // handle the signals before the terminal needs to be restored
+    let update_interval = Duration::from_secs_f32(1.0 / opts.ups.unwrap_or(cfg.ups));
+    let mut events = Box::pin(events::stream(idle_cl, update_interval)?);
+
+    setup()?;
     let mut term =
-        Terminal::new(CrosstermBackend::new(stdout)).context("Failed to initialize terminal")?;
+        Terminal::new(CrosstermBackend::new(stdout())).context("Failed to initialize terminal")?;
 
-    render(&mut term, &cfg.layout, &mut s)?;
+    draw(&mut term, &screens, &cfg.help, &mut s, &mut cl).await?;
 
     let clear_query_on_play = opts.clear_query_on_play
         || if opts.no_clear_query_on_play {
@@ -136,146 +286,203 @@ async fn run() -> Result<()> {
             cfg.clear_query_on_play
         };
     let cycle = opts.cycle || if opts.no_cycle { false } else { cfg.cycle };
+    // mpd can't match fuzzily, so every word would load the whole queue in lazy mode
+    let fuzzy_search = !lazy_queue
+        && (opts.fuzzy_search
+            || if opts.no_fuzzy_search {
+                false
+            } else {
+                cfg.fuzzy_search
+            });
     let jump_lines = opts.jump_lines.unwrap_or(cfg.jump_lines);
     let seek_secs = opts.seek_secs.unwrap_or(cfg.seek_secs);
+    let message_timeout =
+        Duration::from_secs_f32(opts.message_timeout.unwrap_or(cfg.message_timeout));
 
-    let seek_backwards = format!("seekcur -{seek_secs}");
-    let seek_backwards = seek_backwards.as_bytes();
-    let seek_forwards = format!("seekcur +{seek_secs}");
-    let seek_forwards = seek_forwards.as_bytes();
-    let update_interval = Duration::from_secs_f32(1.0 / opts.ups.unwrap_or(cfg.ups));
+    let mut keys = Keys::new(Duration::from_secs_f32(
+        opts.key_timeout.unwrap_or(cfg.key_timeout),
+    ));
 
-    let t1 = thread::current();
-    let t2 = Thread::clone(&t1);
-    let t3 = Thread::clone(&t1);
-    // update status: 0b100
-    // update queue:  0b010
-    // update frame:  0b001
-    let updates = Arc::new(AtomicU8::new(0b000));
-    let updates1 = Arc::clone(&updates);
-    let updates2 = Arc::clone(&updates);
-    let updates3 = Arc::clone(&updates);
-    let cmds = Arc::new(SegQueue::new());
-    let cmds1 = Arc::clone(&cmds);
-
-    thread::spawn(move || {
-        block_on(async move {
-            loop {
-                updates1.fetch_or(
-                    match idle_cl.idle().await {
-                        Ok((true, true)) => 0b111,
-                        Ok((true, false)) => 0b101,
-                        Ok((false, true)) => 0b011,
-                        Ok(_) => continue,
-                        Err(e) => {
-                            eprintln!("{:?}", cleanup().map_or_else(|x| x, |_| e));
-                            exit(1);
-                        }
-                    },
-                    Ordering::Relaxed,
-                );
-                t1.unpark();
-            }
-        })
-    });
+    // an event received while checking for more events before searching on the server
+    let mut peeked = None;
+    let history_size = opts.history_size.unwrap_or(cfg.history_size);
+    let mut history = History::load("command_history", history_size)?;
+    let mut search_history = History::load("search_history", history_size)?;
+    let mut completer = Completer::default();
+    let mut queued = None;
+    let mut last_click: Option<(Instant, (usize, usize))> = None;
+    let mut dragging = None;
+    let mut searched = String::new();
 
-    thread::spawn(move || {
-        block_on(async move {
-            let mut timer = Timer::interval(update_interval);
-            loop {
-                updates2.fetch_or(0b101, Ordering::Relaxed);
-                t2.unpark();
-                timer.next().await;
-            }
-        })
-    });
+    loop {
+        let mut updates = 0;
+        let cmd = if let Some(cmd) = queued.take() {
+            Some(cmd)
+        } else {
+            let ev = if let Some(ev) = peeked.take() {
+                ev
+            } else {
+                // wait for the next event until the pending keys or the message expire
+                let timeout = [keys.deadline(), s.message_deadline]
+                    .into_iter()
+                    .flatten()
+                    .min()
+                    .map_or_else(Timer::never, Timer::at);
+                events
+                    .next()
+                    .or(async {
+                        timeout.await;
+                        Some(Ok(Event::Timeout))
+                    })
+                    .await
+            };
+            let Some(ev) = ev else {
+                return Ok(());
+            };
 
-    thread::spawn(move || {
-        let mut searching = false;
-        while let Ok(ev) = event::read() {
-            cmds1.push(match ev {
-                Event::Mouse(MouseEvent {
+            match ev? {
+                Event::Input(event::Event::Key(ev)) => keys.input(ev, &s),
+                Event::Input(event::Event::Paste(x)) => Some((Command::Paste(x), None)),
+                Event::Input(event::Event::Mouse(MouseEvent {
                     kind: MouseEventKind::ScrollUp,
                     ..
-                }) => Command::Up,
-                Event::Mouse(MouseEvent {
+                })) => Some((Command::Up, None)),
+                Event::Input(event::Event::Mouse(MouseEvent {
                     kind: MouseEventKind::ScrollDown,
                     ..
-                }) => Command::Down,
-                Event::Resize(..) => {
-                    updates3.fetch_or(0b001, Ordering::Relaxed);
-                    t3.unpark();
-                    continue;
-                }
-                Event::Key(KeyEvent {
-                    code, modifiers, ..
-                }) => match code {
-                    KeyCode::Char('q') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        Command::Quit
-                    }
-                    KeyCode::Char('u') if modifiers.contains(KeyModifiers::CONTROL) => {
-                        if searching {
-                            Command::ClearSearch
-                        } else {
-                            Command::JumpUp
+                })) => Some((Command::Down, None)),
+                Event::Input(event::Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Down(MouseButton::Left),
+                    column,
+                    row,
+                    ..
+                })) => {
+                    dragging = None;
+                    match s.clicked(column, row) {
+                        Some((_, Clickable::Row(pane, i))) => {
+                            if last_click.take().is_some_and(|(t, x)| {
+                                x == (pane, i) && t.elapsed() < Duration::from_millis(500)
+                            }) {
+                                queued = Some((Command::Play, None));
+                            } else {
+                                last_click = Some((Instant::now(), (pane, i)));
+                            }
+                            s.set_focus(pane);
+                            Some((Command::Select(i), None))
                         }
-                    }
-                    KeyCode::Left => Command::SeekBackwards,
-                    KeyCode::Right => Command::SeekForwards,
-                    KeyCode::Down => Command::Down,
-                    KeyCode::Up => Command::Up,
-                    KeyCode::PageDown => Command::JumpDown,
-                    KeyCode::PageUp => Command::JumpUp,
-                    KeyCode::Enter if searching => {
-                        searching = false;
-                        Command::Searching(false)
-                    }
-                    KeyCode::Enter => Command::Play,
-                    KeyCode::Backspace if searching => Command::BackspaceSearch,
-                    KeyCode::Esc => {
-                        searching = false;
-                        Command::QuitSearch
-                    }
-                    KeyCode::Char(c) if searching => Command::InputSearch(c),
-                    KeyCode::Char(c) => match c {
-                        'q' => Command::Quit,
-                        'r' => Command::ToggleRepeat,
-                        'R' => Command::ToggleRandom,
-                        's' => Command::ToggleSingle,
-                        'S' => Command::ToggleOneshot,
-                        'c' => Command::ToggleConsume,
-                        'p' => Command::TogglePause,
-                        ';' => Command::Stop,
-                        'h' => Command::SeekBackwards,
-                        'l' => Command::SeekForwards,
-                        'H' => Command::Previous,
-                        'L' => Command::Next,
-                        ' ' => Command::Reselect,
-                        'j' => Command::Down,
-                        'k' => Command::Up,
-                        'J' => Command::JumpDown,
-                        'd' if modifiers.contains(KeyModifiers::CONTROL) => Command::JumpDown,
-                        'K' => Command::JumpUp,
-                        'g' => Command::GotoTop,
-                        'G' => Command::GotoBottom,
-                        '/' => {
-                            searching = true;
-                            Command::Searching(true)
+                        Some((_, Clickable::Screen(i))) => Some((Command::NextScreen, Some(i + 1))),
+                        Some((_, Clickable::Button(cmd))) => Some((cmd, None)),
+                        Some((area, Clickable::Gauge(gauge))) => {
+                            dragging = Some((area, gauge));
+                            Some((gauge_command(area, gauge, column, &s), None))
                         }
-                        _ => continue,
-                    },
-                    _ => continue,
+                        None => continue,
+                    }
+                }
+                Event::Input(event::Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Drag(MouseButton::Left),
+                    column,
+                    ..
+                })) => match dragging {
+                    Some((area, gauge)) => Some((gauge_command(area, gauge, column, &s), None)),
+                    None => continue,
                 },
-                _ => continue,
-            });
-            t3.unpark();
+                Event::Input(event::Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Up(MouseButton::Left),
+                    ..
+                })) => {
+                    dragging = None;
+                    continue;
+                }
+                Event::Input(event::Event::Resize(..)) => {
+                    updates = FRAME;
+                    None
+                }
+                Event::Input(_) => continue,
+                Event::Idle(status, queue) => {
+                    updates = match (status, queue) {
+                        (true, true) => STATUS | QUEUE | FRAME,
+                        // redrawn only if the status turns out to be different
+                        (true, false) => STATUS,
+                        (false, true) => QUEUE | FRAME,
+                        (false, false) => continue,
+                    };
+                    None
+                }
+                Event::Tick => {
+                    updates = TICK;
+                    None
+                }
+                #[cfg(unix)]
+                Event::Signal(SIGTSTP) => Some((Command::Suspend, None)),
+                // the shell may have reset the terminal, and the idle connection may have missed
+                // changes while stopped
+                #[cfg(unix)]
+                Event::Signal(SIGCONT) => {
+                    disable_raw_mode().context("Failed to disable raw mode")?;
+                    setup()?;
+                    term.clear().context("Failed to clear terminal")?;
+                    updates = STATUS | QUEUE | FRAME;
+                    None
+                }
+                #[cfg(unix)]
+                Event::Signal(_) => continue,
+                Event::Timeout => keys.expire(&s),
+            }
+        };
+
+        let pending = keys.pending();
+        if pending != s.pending {
+            s.pending = pending;
+            updates |= FRAME;
         }
-    });
 
-    loop {
-        let updates = if let Some(cmd) = cmds.pop() {
-            (match cmd {
+        // shown after the command unless mpd responded with an error
+        let mut info = None;
+        updates |= if let Some((cmd, count)) = cmd {
+            match cmd {
                 Command::Quit => return Ok(()),
+                #[cfg(unix)]
+                Command::Suspend => {
+                    // the terminal is set up again once continued, and the whole job is stopped
+                    // like the shell does with ctrl-z outside of raw mode
+                    cleanup()?;
+                    process::kill_current_process_group(Signal::Stop).context("Failed to stop")?;
+                    0
+                }
+                #[cfg(not(unix))]
+                Command::Suspend => {
+                    s.notify(
+                        "Suspending is not supported on this platform".into(),
+                        true,
+                        message_timeout,
+                    );
+                    FRAME
+                }
+                cmd @ (Command::Down
+                | Command::Up
+                | Command::JumpDown
+                | Command::JumpUp
+                | Command::GotoTop
+                | Command::GotoBottom
+                | Command::InputSearch(_)
+                | Command::BackspaceSearch
+                | Command::ClearSearch
+                | Command::QuitSearch
+                | Command::Searching(_)
+                | Command::EditSearch(_)
+                | Command::PreviousHistory
+                | Command::NextHistory
+                | Command::Paste(_))
+                    if s.help.is_some() =>
+                {
+                    s.help_command(cmd, count, jump_lines);
+                    FRAME
+                }
+                cmd @ (Command::Down | Command::Up) if s.modal.is_some() => {
+                    s.modal_command(cmd, count);
+                    FRAME
+                }
                 Command::ToggleRepeat => {
                     cl.command(if s.status.repeat {
                         b"repeat 0"
@@ -284,7 +491,7 @@ async fn run() -> Result<()> {
                     })
                     .await
                     .context("Failed to toggle repeat")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::ToggleRandom => {
                     cl.command(if s.status.random {
@@ -294,7 +501,7 @@ async fn run() -> Result<()> {
                     })
                     .await
                     .context("Failed to toggle random")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::ToggleSingle => {
                     cl.command(if s.status.single == Some(true) {
@@ -304,13 +511,13 @@ async fn run() -> Result<()> {
                     })
                     .await
                     .context("Failed to toggle single")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::ToggleOneshot => {
                     cl.command(s.status.single.map_or(b"single 0", |_| b"single oneshot"))
                         .await
                         .context("Failed to toggle oneshot")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::ToggleConsume => {
                     cl.command(if s.status.consume {
@@ -320,7 +527,7 @@ async fn run() -> Result<()> {
                     })
                     .await
                     .context("Failed to toggle consume")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::TogglePause => {
                     cl.command(match s.status.state {
@@ -330,46 +537,101 @@ async fn run() -> Result<()> {
                     })
                     .await
                     .context("Failed to toggle pause")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::Stop => {
                     cl.command(b"stop")
                         .await
                         .context("Failed to stop playing")?;
-                    0b101
+                    STATUS | FRAME
                 }
                 Command::SeekBackwards => {
-                    cl.command(seek_backwards)
-                        .await
-                        .context("Failed to seek backwards")?;
-                    0b101
+                    cl.command(
+                        format!("seekcur -{}", seek_secs * count.unwrap_or(1) as f32).as_bytes(),
+                    )
+                    .await
+                    .context("Failed to seek backwards")?;
+                    STATUS | FRAME
                 }
                 Command::SeekForwards => {
-                    cl.command(seek_forwards)
+                    cl.command(
+                        format!("seekcur +{}", seek_secs * count.unwrap_or(1) as f32).as_bytes(),
+                    )
+                    .await
+                    .context("Failed to seek forwards")?;
+                    STATUS | FRAME
+                }
+                Command::SeekTo(x) => {
+                    let time = if let Some(track) = s
+                        .status
+                        .song
+                        .as_ref()
+                        .and_then(|song| s.queue.get(song.pos))
+                    {
+                        track.time
+                    } else {
+                        continue;
+                    };
+                    cl.command(format!("seekcur {}", x * f32::from(time)).as_bytes())
                         .await
-                        .context("Failed to seek forwards")?;
-                    0b101
+                        .context("Failed to seek")?;
+                    STATUS | FRAME
                 }
-                Command::Previous => {
-                    cl.command(b"previous")
+                Command::SetVolume(x) => {
+                    cl.command(format!("setvol {}", min(x, 100)).as_bytes())
                         .await
-                        .context("Failed to play previous song")?;
-                    0b101
+                        .context("Failed to set the volume")?;
+                    STATUS | FRAME
+                }
+                Command::Previous => {
+                    // going around the queue more than once changes nothing
+                    for _ in 0 .. min(count.unwrap_or(1), s.status.queue_len) {
+                        cl.command(b"previous")
+                            .await
+                            .context("Failed to play previous song")?;
+                    }
+                    STATUS | FRAME
                 }
                 Command::Next => {
-                    cl.command(b"next")
+                    // going around the queue more than once changes nothing
+                    for _ in 0 .. min(count.unwrap_or(1), s.status.queue_len) {
+                        cl.command(b"next")
+                            .await
+                            .context("Failed to play next song")?;
+                    }
+                    STATUS | FRAME
+                }
+                Command::Play if s.has_marks() => {
+                    let xs = targets(&mut s, &mut cl).await?;
+                    cl.load_queue(&mut s.queue, xs.iter().copied()).await?;
+                    // the songs that failed to load are left out
+                    let xs: Vec<_> = xs
+                        .into_iter()
+                        .filter_map(|x| Some((x, s.queue.get(x)?.id)))
+                        .collect();
+                    if xs.is_empty() {
+                        continue;
+                    }
+                    let cur = s.status.song.as_ref().map(|song| song.pos);
+                    let mut cmds = Vec::with_capacity(xs.len() + 1);
+
+                    // move the marked songs right after the current song in order
+                    for (id, dest) in app::moves_after(&xs, cur) {
+                        cmds.push(format!("moveid {id} {dest}"));
+                    }
+                    cmds.push(format!("playid {}", xs[0].1));
+
+                    cl.command_list(&cmds)
                         .await
-                        .context("Failed to play next song")?;
-                    0b101
+                        .context("Failed to play the marked songs")?;
+                    s.clear_marks();
+                    if clear_query_on_play {
+                        s.quit_search();
+                    }
+                    STATUS | QUEUE | FRAME
                 }
                 Command::Play => {
-                    cl.play(if s.query.is_empty() {
-                        if s.selected < s.queue.len() {
-                            s.selected
-                        } else {
-                            continue;
-                        }
-                    } else if let Some(&x) = s.filtered.get(s.selected) {
+                    cl.play(if let Some(x) = s.queue_pos(s.selected()) {
                         x
                     } else {
                         continue;
@@ -379,145 +641,605 @@ async fn run() -> Result<()> {
                     if clear_query_on_play {
                         s.quit_search();
                     }
-                    0b101
+                    STATUS | FRAME
+                }
+                Command::Delete => {
+                    let xs: Vec<_> = if s.has_marks() {
+                        targets(&mut s, &mut cl).await?
+                    } else {
+                        (s.selected() .. s.selected().saturating_add(count.unwrap_or(1)))
+                            .map_while(|i| s.queue_pos(i))
+                            .collect()
+                    };
+                    cl.load_queue(&mut s.queue, xs.iter().copied()).await?;
+                    let cmds: Vec<_> = xs
+                        .into_iter()
+                        .filter_map(|x| s.queue.get(x))
+                        .map(|track| format!("deleteid {}", track.id))
+                        .collect();
+                    info = Some(format!("Deleted {} songs", cmds.len()));
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to delete the selected songs")?;
+                    s.clear_marks();
+                    QUEUE | FRAME
+                }
+                Command::Select(i) => {
+                    if i >= s.len() {
+                        continue;
+                    }
+                    s.select(i);
+                    FRAME
                 }
                 Command::Reselect => {
                     s.reselect();
-                    0b001
+                    FRAME
                 }
                 Command::Down => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if s.selected == len - 1 {
-                        if cycle {
-                            s.select(0);
-                        }
-                    } else {
-                        s.select(s.selected + 1);
-                    }
-                    0b001
+                    s.down(count.unwrap_or(1), cycle);
+                    FRAME
                 }
                 Command::Up => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if s.selected == 0 {
-                        if cycle {
-                            s.select(len - 1);
-                        }
-                    } else {
-                        s.select(s.selected - 1);
-                    }
-                    0b001
+                    s.up(count.unwrap_or(1), cycle);
+                    FRAME
                 }
                 Command::JumpDown => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if cycle {
-                        s.select((s.selected + jump_lines) % len);
-                    } else {
-                        s.select(min(s.selected + jump_lines, len - 1));
-                    };
-                    0b001
+                    s.down(jump_lines.saturating_mul(count.unwrap_or(1)), cycle);
+                    FRAME
                 }
                 Command::JumpUp => {
-                    let len = s.len();
-                    if s.selected >= len {
-                        s.reselect();
-                    } else if cycle {
-                        while s.selected < jump_lines {
-                            s.selected += len;
-                        }
-                        s.selected -= jump_lines;
-                        s.liststate.select(Some(s.selected));
-                    } else if s.selected < jump_lines {
-                        s.select(0);
-                    } else {
-                        s.select(s.selected - jump_lines);
-                    };
-                    0b001
+                    s.up(jump_lines.saturating_mul(count.unwrap_or(1)), cycle);
+                    FRAME
                 }
                 Command::GotoTop => {
-                    s.select(0);
-                    0b001
+                    s.select(count.map_or(0, |n| min(n, s.len()).saturating_sub(1)));
+                    FRAME
                 }
                 Command::GotoBottom => {
                     let len = s.len();
                     if len == 0 {
                         continue;
                     }
-                    s.select(len - 1);
-                    0b001
+                    s.select(count.map_or(len, |n| min(n, len)) - 1);
+                    FRAME
                 }
                 Command::InputSearch(c) => {
-                    let empty = s.query.is_empty();
-                    s.query.push(c);
-                    if empty {
-                        s.update_search(&queue_strings);
-                    } else {
-                        let query = s.query.to_lowercase();
-                        s.filtered.retain(|&i| queue_strings[i].contains(&query));
-                    }
-                    0b001
+                    s.edit_query(
+                        |query| query.insert(c),
+                        &queue_strings,
+                        fuzzy_search,
+                        &cfg.search_folding,
+                    );
+                    FRAME
                 }
                 Command::BackspaceSearch => {
-                    let c = s.query.pop();
-                    if !s.query.is_empty() {
-                        s.update_search(&queue_strings);
-                    } else if c.is_some() {
-                        s.reselect();
-                    }
-                    0b001
+                    s.edit_query(
+                        LineInput::backspace,
+                        &queue_strings,
+                        fuzzy_search,
+                        &cfg.search_folding,
+                    );
+                    FRAME
+                }
+                Command::EditSearch(edit) => {
+                    s.edit_query(
+                        |query| query.edit(edit),
+                        &queue_strings,
+                        fuzzy_search,
+                        &cfg.search_folding,
+                    );
+                    FRAME
                 }
                 Command::ClearSearch => {
-                    if !s.query.is_empty() {
-                        s.query.clear();
-                        s.reselect();
-                    }
-                    0b001
+                    s.edit_query(
+                        LineInput::clear,
+                        &queue_strings,
+                        fuzzy_search,
+                        &cfg.search_folding,
+                    );
+                    FRAME
                 }
                 Command::QuitSearch => {
                     s.quit_search();
-                    0b001
+                    search_history.reset();
+                    FRAME
                 }
                 Command::Searching(x) => {
+                    if x {
+                        s.set_finding(false, &queue_strings, fuzzy_search, &cfg.search_folding);
+                    } else if s.query.is_empty() {
+                        s.finding = false;
+                    } else if let Err(e) = search_history.push(s.query.as_str()) {
+                        // the search still goes on without saving it
+                        s.notify(format!("{e:#}"), true, message_timeout);
+                    }
+                    search_history.reset();
                     s.searching = x;
-                    0b001
+                    s.visual = None;
+                    FRAME
                 }
-            }) | updates.swap(0b000, Ordering::SeqCst)
-        } else {
-            match updates.swap(0b000, Ordering::SeqCst) {
-                // wait for more commands or updates if neither were received
-                x if x == 0b000 => {
-                    thread::park();
-                    continue;
+                Command::Find => {
+                    s.set_finding(true, &queue_strings, fuzzy_search, &cfg.search_folding);
+                    search_history.reset();
+                    s.searching = true;
+                    s.visual = None;
+                    FRAME
+                }
+                cmd @ (Command::NextMatch | Command::PreviousMatch) => {
+                    if s.query.is_empty() {
+                        continue;
+                    }
+                    let n = count.unwrap_or(1);
+                    let next = matches!(cmd, Command::NextMatch);
+                    if !s.finding {
+                        // every song matches when filtering
+                        if next {
+                            s.down(n, true);
+                        } else {
+                            s.up(n, true);
+                        }
+                    } else if s.filtered.is_empty() {
+                        info = Some(String::from("No matches"));
+                    } else if next {
+                        s.next_match(n);
+                    } else {
+                        s.previous_match(n);
+                    }
+                    FRAME
+                }
+                Command::InputCmdline(c) => {
+                    s.cmdline.push(c);
+                    FRAME
+                }
+                Command::BackspaceCmdline => {
+                    if s.cmdline.pop().is_none() {
+                        s.quit_command();
+                        history.reset();
+                    }
+                    FRAME
+                }
+                Command::ClearCmdline => {
+                    s.cmdline.clear();
+                    FRAME
+                }
+                Command::CompleteCmdline => {
+                    let candidates = completer
+                        .complete(&mut cl, &mut s.cmdline)
+                        .await
+                        .context("Failed to complete command")?;
+                    if !candidates.is_empty() {
+                        s.output = candidates;
+                        s.output_offset = 0;
+                    }
+                    FRAME
+                }
+                Command::PreviousHistory if s.searching => {
+                    if let Some(x) = search_history.previous(s.query.as_str()) {
+                        s.edit_query(
+                            |query| query.set(x),
+                            &queue_strings,
+                            fuzzy_search,
+                            &cfg.search_folding,
+                        );
+                    }
+                    FRAME
+                }
+                Command::NextHistory if s.searching => {
+                    if let Some(x) = search_history.next() {
+                        s.edit_query(
+                            |query| query.set(x),
+                            &queue_strings,
+                            fuzzy_search,
+                            &cfg.search_folding,
+                        );
+                    }
+                    FRAME
+                }
+                Command::PreviousHistory => {
+                    if let Some(x) = history.previous(&s.cmdline) {
+                        s.cmdline.clear();
+                        s.cmdline.push_str(x);
+                    }
+                    FRAME
+                }
+                Command::NextHistory => {
+                    if let Some(x) = history.next() {
+                        s.cmdline.clear();
+                        s.cmdline.push_str(x);
+                    }
+                    FRAME
+                }
+                Command::RunCmdline => {
+                    let line = mem::take(&mut s.cmdline);
+                    let line = line.trim();
+                    s.commanding = false;
+                    if let Err(e) = history.push(line) {
+                        // the command still runs without saving it
+                        s.notify(format!("{e:#}"), true, message_timeout);
+                    }
+
+                    if let Some(cmd) = cmdline::parse_action(line) {
+                        queued = Some(cmd);
+                        FRAME
+                    } else if let Some(
+                        name @ ("idle"
+                        | "noidle"
+                        | "close"
+                        | "albumart"
+                        | "readpicture"
+                        | "command_list_begin"
+                        | "command_list_ok_begin"),
+                    ) = line.split_whitespace().next()
+                    {
+                        s.output = vec![format!("mmtc: unsupported command: {name}")];
+                        s.output_offset = 0;
+                        FRAME
+                    } else if line.is_empty() {
+                        FRAME
+                    } else {
+                        s.output = cl
+                            .command_lines(line.as_bytes())
+                            .await
+                            .context("Failed to run command")?;
+                        s.output_offset = 0;
+                        STATUS | FRAME
+                    }
+                }
+                Command::QuitCmdline => {
+                    s.quit_command();
+                    s.output.clear();
+                    history.reset();
+                    FRAME
+                }
+                Command::Commanding(x) => {
+                    s.commanding = x;
+                    FRAME
+                }
+                Command::ClearOutput => {
+                    s.output.clear();
+                    FRAME
+                }
+                Command::ScrollOutputUp => {
+                    s.output_offset = s
+                        .output_offset
+                        .saturating_sub(jump_lines.saturating_mul(count.unwrap_or(1)));
+                    FRAME
+                }
+                Command::ScrollOutputDown => {
+                    s.output_offset = min(
+                        s.output_offset
+                            .saturating_add(jump_lines.saturating_mul(count.unwrap_or(1))),
+                        s.output.len().saturating_sub(1),
+                    );
+                    FRAME
+                }
+                Command::ToggleMark => {
+                    let n = count.unwrap_or(1);
+                    let xs: Vec<_> = (s.selected() .. s.selected().saturating_add(n))
+                        .map_while(|i| s.queue_pos(i))
+                        .collect();
+                    cl.load_queue(&mut s.queue, xs).await?;
+                    s.toggle_marks(n);
+                    FRAME
+                }
+                Command::ToggleVisual => {
+                    if s.visual.is_some() {
+                        let xs = s.targets();
+                        cl.load_queue(&mut s.queue, xs).await?;
+                        s.commit_visual();
+                    } else if s.selected() < s.len() {
+                        s.visual = Some(s.selected());
+                    }
+                    FRAME
+                }
+                Command::QuitVisual => {
+                    s.visual = None;
+                    FRAME
+                }
+                Command::MarkAll => {
+                    let xs = s.markable();
+                    cl.load_queue(&mut s.queue, xs).await?;
+                    s.mark_all();
+                    FRAME
+                }
+                Command::ClearMarks => {
+                    s.clear_marks();
+                    FRAME
+                }
+                Command::MoveUp => {
+                    let mut xs = targets(&mut s, &mut cl).await?;
+                    if xs.is_empty() {
+                        continue;
+                    }
+                    let mut cmds = Vec::new();
+                    for _ in 0 .. min(count.unwrap_or(1), s.queue.len()) {
+                        // songs blocked by the top of the queue or other blocked songs stay
+                        let mut floor = 0;
+                        for x in &mut xs {
+                            if *x > floor {
+                                cmds.push(format!("move {} {}", *x, *x - 1));
+                                *x -= 1;
+                            }
+                            floor = *x + 1;
+                        }
+                    }
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to move the selected songs")?;
+                    if !s.has_marks() && !s.filtering() {
+                        s.select(xs[0]);
+                    }
+                    QUEUE | FRAME
+                }
+                Command::MoveDown => {
+                    let mut xs = targets(&mut s, &mut cl).await?;
+                    if xs.is_empty() {
+                        continue;
+                    }
+                    let mut cmds = Vec::new();
+                    for _ in 0 .. min(count.unwrap_or(1), s.queue.len()) {
+                        // songs blocked by the bottom of the queue or other blocked songs stay
+                        let mut ceil = s.queue.len();
+                        for x in xs.iter_mut().rev() {
+                            if *x + 1 < ceil {
+                                cmds.push(format!("move {} {}", *x, *x + 1));
+                                *x += 1;
+                            }
+                            ceil = *x;
+                        }
+                    }
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to move the selected songs")?;
+                    if !s.has_marks() && !s.filtering() {
+                        s.select(xs[0]);
+                    }
+                    QUEUE | FRAME
+                }
+                Command::Priority => {
+                    let xs = targets(&mut s, &mut cl).await?;
+                    cl.load_queue(&mut s.queue, xs.iter().copied()).await?;
+                    let mut cmd = format!("prioid {}", min(count.unwrap_or(0), 255));
+                    for track in xs.into_iter().filter_map(|x| s.queue.get(x)) {
+                        cmd.push(' ');
+                        cmd.push_str(&track.id.to_string());
+                    }
+                    cl.command(cmd.as_bytes())
+                        .await
+                        .context("Failed to set the priority of the selected songs")?;
+                    FRAME
+                }
+                Command::AddToPlaylist(name) if name.is_empty() => {
+                    let items = cl
+                        .playlists()
+                        .await
+                        .context("Failed to list the playlists")?;
+                    if items.is_empty() {
+                        info = Some(String::from("There are no playlists"));
+                    } else {
+                        s.modal = Some(Modal::Pick {
+                            prompt: String::from("Add to playlist"),
+                            items,
+                            selected: 0,
+                            cmd: Command::AddToPlaylist,
+                        });
+                    }
+                    FRAME
+                }
+                Command::AddToPlaylist(name) => {
+                    let quoted = mpd::quote(&name);
+                    let xs = targets(&mut s, &mut cl).await?;
+                    cl.load_queue(&mut s.queue, xs.iter().copied()).await?;
+                    let cmds: Vec<_> = xs
+                        .into_iter()
+                        .filter_map(|x| s.queue.get(x))
+                        .map(|track| format!("playlistadd {quoted} {}", mpd::quote(&track.file)))
+                        .collect();
+                    info = Some(format!("Added {} songs to {name}", cmds.len()));
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to add the selected songs to the playlist")?;
+                    s.clear_marks();
+                    FRAME
+                }
+                Command::Clear => {
+                    s.modal = Some(Modal::Confirm {
+                        prompt: String::from("Clear the queue?"),
+                        cmd: Command::ForceClear,
+                    });
+                    FRAME
+                }
+                Command::ForceClear => {
+                    cl.command(b"clear")
+                        .await
+                        .context("Failed to clear the queue")?;
+                    info = Some(String::from("Cleared the queue"));
+                    QUEUE | FRAME
+                }
+                Command::Save(name) if name.is_empty() => {
+                    s.modal = Some(Modal::Input {
+                        prompt: String::from("Save the queue as"),
+                        input: String::new(),
+                        cmd: Command::Save,
+                    });
+                    FRAME
+                }
+                Command::Save(name) => {
+                    if cl
+                        .playlists()
+                        .await
+                        .context("Failed to list the playlists")?
+                        .contains(&name)
+                    {
+                        s.modal = Some(Modal::Confirm {
+                            prompt: format!("Overwrite playlist {name}?"),
+                            cmd: Command::ForceSave(name),
+                        });
+                        FRAME
+                    } else {
+                        queued = Some((Command::ForceSave(name), None));
+                        0
+                    }
+                }
+                Command::ForceSave(name) => {
+                    let quoted = mpd::quote(&name);
+                    let mut cmds = vec![format!("save {quoted}")];
+                    if cl
+                        .playlists()
+                        .await
+                        .context("Failed to list the playlists")?
+                        .contains(&name)
+                    {
+                        cmds.insert(0, format!("rm {quoted}"));
+                    }
+                    cl.command_list(&cmds)
+                        .await
+                        .context("Failed to save the queue")?;
+                    info = Some(format!("Saved the queue to {name}"));
+                    FRAME
+                }
+                Command::NextScreen => {
+                    let len = screens.len();
+                    s.switch_screen(
+                        count.map_or((s.screen + 1) % len, |n| min(n, len).saturating_sub(1)),
+                    );
+                    FRAME
+                }
+                Command::PreviousScreen => {
+                    let len = screens.len();
+                    s.switch_screen((s.screen + len - count.unwrap_or(1) % len) % len);
+                    FRAME
+                }
+                Command::FocusNext => {
+                    let len = s.screen_states[s.screen].panes.len();
+                    s.set_focus((s.focus() + count.unwrap_or(1)) % len);
+                    FRAME
+                }
+                Command::FocusPrevious => {
+                    let len = s.screen_states[s.screen].panes.len();
+                    s.set_focus((s.focus() + len - count.unwrap_or(1) % len) % len);
+                    FRAME
+                }
+                Command::Help => {
+                    s.help = Some(Help::default());
+                    s.update_help();
+                    FRAME
+                }
+                Command::QuitHelp => {
+                    s.help = None;
+                    FRAME
+                }
+                Command::Paste(x) => {
+                    if !s.searching || s.modal.is_some() || s.commanding {
+                        continue;
+                    }
+                    s.edit_query(
+                        |query| query.insert_str(&x),
+                        &queue_strings,
+                        fuzzy_search,
+                        &cfg.search_folding,
+                    );
+                    FRAME
+                }
+                cmd @ (Command::ConfirmModal
+                | Command::QuitModal
+                | Command::InputModal(_)
+                | Command::BackspaceModal
+                | Command::ClearModal) => {
+                    queued = s.modal_command(cmd, count).map(|cmd| (cmd, None));
+                    FRAME
                 }
-                x => x,
             }
+        } else if s.expire_message() {
+            FRAME
+        } else {
+            0
         };
 
+        if let Some(ack) = cl.take_ack() {
+            s.notify(ack, true, message_timeout);
+            updates |= FRAME;
+        } else if let Some(info) = info {
+            s.notify(info, false, message_timeout);
+            updates |= FRAME;
+        }
+
+        // redraw the elapsed time counted since the last status while playing,
+        // fetching the status again once in a while in case it drifted
+        if updates & TICK != 0 && s.status.state == PlayerState::Play {
+            updates |= if s.status_time.elapsed() >= RESYNC {
+                STATUS | FRAME
+            } else {
+                FRAME
+            };
+        }
+
         // conditionally update status
-        if updates & 0b100 == 0b100 {
-            s.status = cl.status().await?;
+        // and only redraw when it has changed
+        if updates & STATUS != 0 {
+            let status = cl.status().await?;
+            if status != s.status {
+                updates |= FRAME;
+            }
+            s.status = status;
+            s.status_time = Instant::now();
         }
 
         // conditionally update queue
-        if updates & 0b010 == 0b010 {
-            let queue = cl.queue(s.status.queue_len, &cfg.search_fields).await?;
-            s.queue = queue.0;
-            queue_strings = queue.1;
-            s.liststate.select(None);
-            s.reselect();
-            if !s.query.is_empty() {
-                s.update_search(&queue_strings);
+        if updates & QUEUE != 0 {
+            if lazy_queue {
+                // drop the loaded songs, the ones in view are loaded again when drawing
+                s.status = cl.status().await?;
+                s.status_time = Instant::now();
+                s.queue = Queue::new(s.status.queue_len);
+                searched.clear();
+            } else {
+                let queue = cl
+                    .queue(s.status.queue_len, &cfg.search_fields, &cfg.search_folding)
+                    .await?;
+                s.queue = queue.0;
+                queue_strings = queue.1;
+                if !s.marked.is_empty() {
+                    let ids: HashSet<_> = s.queue.iter().map(|(_, track)| track.id).collect();
+                    s.marked.retain(|id| ids.contains(id));
+                }
+            }
+            s.visual = None;
+            s.reload_panes();
+            if !lazy_queue && !s.query.is_empty() {
+                s.update_search(&queue_strings, fuzzy_search, &cfg.search_folding);
+            }
+        }
+
+        // search on the server whenever the query changes in lazy mode,
+        // once there are no more events to handle
+        if lazy_queue && searched != s.query.as_str() && peeked.is_none() {
+            peeked = future::poll_once(events.next()).await;
+        }
+        if lazy_queue && searched != s.query.as_str() && peeked.is_none() {
+            searched = s.query.as_str().into();
+            if searched.is_empty() {
+                queue_strings.clear();
+            } else if let Ok(query) = search::parse(&searched, &cfg.search_folding) {
+                queue_strings = cl
+                    .search_queue(
+                        query.filter().as_deref(),
+                        &mut s.queue,
+                        &cfg.search_fields,
+                        &cfg.search_folding,
+                    )
+                    .await?;
+                s.update_search(&queue_strings, fuzzy_search, &cfg.search_folding);
+                if s.finding {
+                    s.next_match(0);
+                }
             }
+            updates |= FRAME;
         }
 
         // conditionally update frame
-        if updates & 0b001 == 0b001 {
-            render(&mut term, &cfg.layout, &mut s)?;
+        if updates & FRAME != 0 {
+            draw(&mut term, &screens, &cfg.help, &mut s, &mut cl).await?;
         }
     }
 }
@@ -6,13 +6,20 @@ use futures_lite::{
     StreamExt,
 };
 
-use std::io::{stdout, Write};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{stdout, Write},
+};
 
-use crate::config::SearchFields;
+use crate::{
+    config::{SearchFields, SearchFolding},
+    search::{track_string, TrackString},
+};
 
 pub struct Client {
     r: BufReader<ReadHalf<TcpStream>>,
     w: WriteHalf<TcpStream>,
+    ack: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -22,25 +29,27 @@ pub enum PlayerState {
     Stop,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Status {
     pub repeat: bool,
     pub random: bool,
     pub single: Option<bool>, // None: oneshot
     pub consume: bool,
     pub queue_len: usize,
+    pub volume: Option<u8>, // None: no mixer
     pub state: PlayerState,
     pub song: Option<Song>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Song {
     pub pos: usize,
-    pub elapsed: u16,
+    pub elapsed: f32,
 }
 
 #[derive(Debug)]
 pub struct Track {
+    pub id: usize,
     pub file: String,
     pub artist: Option<String>,
     pub album: Option<String>,
@@ -48,35 +57,68 @@ pub struct Track {
     pub time: u16,
 }
 
-fn track_string(track: &Track, search_fields: &SearchFields) -> String {
-    let mut track_string = String::with_capacity(64);
+// the number of songs loaded at once in lazy mode
+const WINDOW: usize = 256;
 
-    if search_fields.file {
-        track_string.push_str(&track.file.to_lowercase());
-        track_string.push('\n');
-    }
+// the songs of the queue by position, only some of them are loaded in lazy mode
+pub struct Queue {
+    len: usize,
+    tracks: BTreeMap<usize, Track>,
+}
 
-    if search_fields.title {
-        if let Some(title) = &track.title {
-            track_string.push_str(&title.to_lowercase());
-            track_string.push('\n');
+impl Queue {
+    pub fn new(len: usize) -> Self {
+        Queue {
+            len,
+            tracks: BTreeMap::new(),
         }
     }
 
-    if search_fields.artist {
-        if let Some(artist) = &track.artist {
-            track_string.push_str(&artist.to_lowercase());
-            track_string.push('\n');
-        }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, x: usize) -> Option<&Track> {
+        self.tracks.get(&x)
+    }
+
+    // the loaded songs with their positions
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Track)> {
+        self.tracks.iter().map(|(&x, track)| (x, track))
     }
 
-    if search_fields.album {
-        if let Some(album) = &track.album {
-            track_string.push_str(&album.to_lowercase());
+    fn insert(&mut self, x: usize, track: Track) {
+        self.tracks.insert(x, track);
+    }
+}
+
+pub fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
         }
+        quoted.push(c);
     }
+    quoted.push('"');
+    quoted
+}
 
-    track_string
+// `ACK [error@command_listNum] {current_command} message_text` -> `current_command: message_text`
+fn ack_message(line: &str) -> String {
+    if let Some((cmd, msg)) = line
+        .split_once('{')
+        .and_then(|(_, line)| line.split_once("} "))
+    {
+        if cmd.is_empty() {
+            msg.into()
+        } else {
+            format!("{cmd}: {msg}")
+        }
+    } else {
+        line.into()
+    }
 }
 
 impl Client {
@@ -86,6 +128,7 @@ impl Client {
             let mut cl = Client {
                 r: BufReader::new(r),
                 w,
+                ack: None,
             };
 
             let buf = &mut [0; 7];
@@ -103,13 +146,16 @@ impl Client {
 
     pub async fn idle(&mut self) -> Result<(bool, bool)> {
         async move {
-            self.w.write_all(b"idle options player playlist\n").await?;
+            self.w
+                .write_all(b"idle mixer options player playlist\n")
+                .await?;
             let mut lines = (&mut self.r).lines();
             let mut status = false;
             let mut queue = false;
 
             while let Some(line) = lines.next().await {
                 match line?.as_bytes() {
+                    b"changed: mixer" => status = true,
                     b"changed: options" => status = true,
                     b"changed: player" => status = true,
                     b"changed: playlist" => queue = true,
@@ -128,72 +174,175 @@ impl Client {
         &mut self,
         len: usize,
         search_fields: &SearchFields,
-    ) -> Result<(Vec<Track>, Vec<String>)> {
-        async move {
-            let mut first = true;
-            let mut tracks = Vec::with_capacity(len);
-            let mut track_strings = Vec::with_capacity(len);
+        folding: &SearchFolding,
+    ) -> Result<(Queue, Vec<TrackString>)> {
+        let mut queue = Queue::new(len);
+        let mut track_strings = Vec::with_capacity(len);
+        self.tracks(b"playlistinfo", |pos, track, tags| {
+            track_strings.push(track_string(pos, &track, tags, search_fields, folding));
+            queue.insert(pos, track);
+        })
+        .await
+        .context("Failed to query queue")?;
+        queue.len = track_strings.len();
+        Ok((queue, track_strings))
+    }
 
-            let mut file: Option<String> = None;
-            let mut artist: Option<String> = None;
-            let mut album: Option<String> = None;
-            let mut title: Option<String> = None;
-            let mut time = None;
+    // load the windows of the queue containing the given positions that are not loaded yet
+    pub async fn load_queue(
+        &mut self,
+        queue: &mut Queue,
+        xs: impl IntoIterator<Item = usize>,
+    ) -> Result<()> {
+        let mut windows: Vec<_> = xs
+            .into_iter()
+            .filter(|&x| x < queue.len && !queue.tracks.contains_key(&x))
+            .map(|x| x / WINDOW)
+            .collect();
+        windows.sort_unstable();
+        windows.dedup();
+
+        for window in windows {
+            let cmd = format!("playlistinfo {}:{}", window * WINDOW, (window + 1) * WINDOW);
+            self.tracks(cmd.as_bytes(), |pos, track, _| queue.insert(pos, track))
+                .await
+                .context("Failed to load queue")?;
+        }
 
-            self.w.write_all(b"playlistinfo\n").await?;
-            let mut lines = (&mut self.r).lines();
+        Ok(())
+    }
 
-            while let Some(line) = lines.next().await {
-                let line = line?;
-                match line.as_bytes() {
-                    b"OK" => break,
-                    expand!([@b"file: ", ..]) => {
-                        if first {
-                            first = false;
-                        } else if let (Some(file), Some(time)) = (file, time) {
-                            let track = Track {
-                                file,
-                                artist,
-                                album,
-                                title,
-                                time,
-                            };
-                            track_strings.push(track_string(&track, search_fields));
-                            tracks.push(track);
-                        } else {
-                            bail!("incomplete playlist response");
-                        }
-
-                        file = Some(line[6 ..].into());
-                        artist = None;
-                        album = None;
-                        title = None;
-                        time = None;
-                    }
-                    expand!([@b"Artist: ", ..]) => artist = Some(line[8 ..].into()),
-                    expand!([@b"Album: ", ..]) => album = Some(line[7 ..].into()),
-                    expand!([@b"Title: ", ..]) => title = Some(line[7 ..].into()),
-                    expand!([@b"Time: ", ..]) => time = Some(line[6 ..].parse()?),
-                    _ => continue,
-                }
+    // load the songs with the given ids that are not loaded yet, wherever they are
+    pub async fn load_ids(
+        &mut self,
+        queue: &mut Queue,
+        ids: impl IntoIterator<Item = usize>,
+    ) -> Result<()> {
+        let loaded: HashSet<_> = queue.tracks.values().map(|track| track.id).collect();
+        for id in ids {
+            if loaded.contains(&id) {
+                continue;
             }
+            self.tracks(format!("playlistid {id}").as_bytes(), |pos, track, _| {
+                queue.insert(pos, track);
+            })
+            .await
+            .context("Failed to load queue")?;
+            // the song was removed from the queue since
+            self.ack = None;
+        }
+
+        Ok(())
+    }
+
+    // search the queue on the server, loading every song found,
+    // or the whole queue without a filter
+    pub async fn search_queue(
+        &mut self,
+        filter: Option<&str>,
+        queue: &mut Queue,
+        search_fields: &SearchFields,
+        folding: &SearchFolding,
+    ) -> Result<Vec<TrackString>> {
+        let cmd = filter.map_or_else(
+            || String::from("playlistinfo"),
+            |filter| format!("playlistsearch {}", quote(filter)),
+        );
+        let mut track_strings = Vec::new();
+        self.tracks(cmd.as_bytes(), |pos, track, tags| {
+            track_strings.push(track_string(pos, &track, tags, search_fields, folding));
+            queue.insert(pos, track);
+        })
+        .await
+        .context("Failed to search queue")?;
+        Ok(track_strings)
+    }
+
+    // run a command listing songs of the queue,
+    // with their position and the other tags for field filters when searching
+    async fn tracks(
+        &mut self,
+        cmd: &[u8],
+        mut f: impl FnMut(usize, Track, &[(String, String)]),
+    ) -> Result<()> {
+        let mut first = true;
+        let mut file: Option<String> = None;
+        let mut artist: Option<String> = None;
+        let mut album: Option<String> = None;
+        let mut title: Option<String> = None;
+        let mut time = None;
+        let mut id = None;
+        let mut pos = None;
+        let mut tags = Vec::new();
+
+        self.w.write_all(cmd).await?;
+        self.w.write_all(b"\n").await?;
+        let mut lines = (&mut self.r).lines();
+
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            match line.as_bytes() {
+                b"OK" => break,
+                expand!([@b"ACK ", ..]) => {
+                    self.ack = Some(ack_message(&line));
+                    break;
+                }
+                expand!([@b"file: ", ..]) => {
+                    if first {
+                        first = false;
+                    } else if let (Some(id), Some(pos), Some(file), Some(time)) =
+                        (id, pos, file, time)
+                    {
+                        let track = Track {
+                            id,
+                            file,
+                            artist,
+                            album,
+                            title,
+                            time,
+                        };
+                        f(pos, track, &tags);
+                    } else {
+                        bail!("incomplete playlist response");
+                    }
 
-            if let (Some(file), Some(time)) = (file, time) {
-                let track = Track {
-                    file,
-                    artist,
-                    album,
-                    title,
-                    time,
-                };
-                track_strings.push(track_string(&track, search_fields));
-                tracks.push(track);
+                    file = Some(line[6 ..].into());
+                    artist = None;
+                    album = None;
+                    title = None;
+                    time = None;
+                    id = None;
+                    pos = None;
+                    tags.clear();
+                }
+                expand!([@b"Artist: ", ..]) => artist = Some(line[8 ..].into()),
+                expand!([@b"Album: ", ..]) => album = Some(line[7 ..].into()),
+                expand!([@b"Title: ", ..]) => title = Some(line[7 ..].into()),
+                expand!([@b"Time: ", ..]) => time = Some(line[6 ..].parse()?),
+                expand!([@b"Id: ", ..]) => id = Some(line[4 ..].parse()?),
+                expand!([@b"Pos: ", ..]) => pos = Some(line[5 ..].parse()?),
+                expand!([@b"Prio: ", ..] | [@b"duration: ", ..]) => continue,
+                _ => {
+                    if let Some((name, x)) = line.split_once(": ") {
+                        tags.push((name.into(), x.into()));
+                    }
+                }
             }
+        }
 
-            Ok((tracks, track_strings))
+        if let (Some(id), Some(pos), Some(file), Some(time)) = (id, pos, file, time) {
+            let track = Track {
+                id,
+                file,
+                artist,
+                album,
+                title,
+                time,
+            };
+            f(pos, track, &tags);
         }
-        .await
-        .context("Failed to query queue")
+
+        Ok(())
     }
 
     pub async fn status(&mut self) -> Result<Status> {
@@ -203,6 +352,7 @@ impl Client {
             let mut single = None;
             let mut consume = None;
             let mut queue_len = None;
+            let mut volume = None;
             let mut state = PlayerState::Stop;
             let mut pos = None;
             let mut elapsed = None;
@@ -224,12 +374,11 @@ impl Client {
                     b"consume: 0" => consume = Some(false),
                     b"consume: 1" => consume = Some(true),
                     expand!([@b"playlistlength: ", ..]) => queue_len = Some(line[16 ..].parse()?),
+                    expand!([@b"volume: ", ..]) => volume = line[8 ..].parse().ok(),
                     b"state: play" => state = PlayerState::Play,
                     b"state: pause" => state = PlayerState::Pause,
                     expand!([@b"song: ", ..]) => pos = Some(line[6 ..].parse()?),
-                    expand!([@b"elapsed: ", ..]) => {
-                        elapsed = Some(line[9 ..].parse::<f32>()?.round() as u16)
-                    }
+                    expand!([@b"elapsed: ", ..]) => elapsed = Some(line[9 ..].parse::<f32>()?),
                     _ => continue,
                 }
             }
@@ -243,6 +392,7 @@ impl Client {
                     single,
                     consume,
                     queue_len,
+                    volume,
                     state,
                     song: if let (Some(pos), Some(elapsed)) = (pos, elapsed) {
                         Some(Song { pos, elapsed })
@@ -262,11 +412,27 @@ impl Client {
         self.w.write_all(b"play ").await?;
         self.w.write_all(pos.to_string().as_bytes()).await?;
         self.w.write_all(b"\n").await?;
+        self.read_ok().await
+    }
+
+    pub async fn command(&mut self, cmd: &[u8]) -> Result<()> {
+        self.w.write_all(cmd).await?;
+        self.w.write_all(b"\n").await?;
+        self.read_ok().await
+    }
+
+    // skip the response, keeping the error if the server acknowledged one
+    async fn read_ok(&mut self) -> Result<()> {
         let mut lines = (&mut self.r).lines();
 
         while let Some(line) = lines.next().await {
-            match line?.as_bytes() {
-                b"OK" | expand!([@b"ACK ", ..]) => break,
+            let line = line?;
+            match line.as_bytes() {
+                b"OK" => break,
+                expand!([@b"ACK ", ..]) => {
+                    self.ack = Some(ack_message(&line));
+                    break;
+                }
                 _ => continue,
             }
         }
@@ -274,19 +440,47 @@ impl Client {
         Ok(())
     }
 
-    pub async fn command(&mut self, cmd: &[u8]) -> Result<()> {
+    // the error of the last command that failed, if it has not been taken yet
+    pub fn take_ack(&mut self) -> Option<String> {
+        self.ack.take()
+    }
+
+    pub async fn command_list(&mut self, cmds: &[String]) -> Result<()> {
+        let mut list = String::from("command_list_begin\n");
+        for cmd in cmds {
+            list.push_str(cmd);
+            list.push('\n');
+        }
+        list.push_str("command_list_end");
+        self.command(list.as_bytes()).await
+    }
+
+    pub async fn command_lines(&mut self, cmd: &[u8]) -> Result<Vec<String>> {
         self.w.write_all(cmd).await?;
         self.w.write_all(b"\n").await?;
+
+        let mut xs = Vec::new();
         let mut lines = (&mut self.r).lines();
 
         while let Some(line) = lines.next().await {
-            match line?.as_bytes() {
-                b"OK" | expand!([@b"ACK ", ..]) => break,
-                _ => continue,
+            let line = line?;
+            let end = matches!(line.as_bytes(), b"OK" | expand!([@b"ACK ", ..]));
+            xs.push(line);
+            if end {
+                break;
             }
         }
 
-        Ok(())
+        Ok(xs)
+    }
+
+    pub async fn playlists(&mut self) -> Result<Vec<String>> {
+        Ok(self
+            .command_lines(b"listplaylists")
+            .await?
+            .into_iter()
+            .filter_map(|line| line.strip_prefix("playlist: ").map(String::from))
+            .collect())
     }
 
     pub async fn command_stdout(&mut self, cmd: &[u8]) -> Result<()> {
@@ -0,0 +1,695 @@
+use regex::{Regex, RegexBuilder};
+use unicode_normalization::char::{decompose_canonical, decompose_compatible, is_combining_mark};
+
+use std::{
+    cmp::{min, Reverse},
+    iter::Peekable,
+    str::Chars,
+};
+
+use crate::{
+    config::{SearchFields, SearchFolding},
+    mpd::Track,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    File,
+    Title,
+    Artist,
+    Album,
+    Other,
+}
+
+// the searchable fields of a track
+pub struct TrackString {
+    pos: usize,
+    fields: Vec<FieldString>,
+}
+
+struct FieldString {
+    field: Field,
+    // lowercased tag name for field filters, e.g. `genre`
+    name: Box<str>,
+    // whether terms without a field filter match this field
+    searched: bool,
+    chars: Vec<char>,
+    // the index of the original character for each folded character, None if they are the same
+    origins: Option<Vec<usize>>,
+}
+
+// the field and the character index of each matched character
+pub type Matched = Vec<(Field, usize)>;
+
+pub enum Query {
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Term(Option<String>, Pattern),
+}
+
+pub enum Pattern {
+    Word(Vec<char>),
+    Phrase(Vec<char>),
+    Regex(Regex),
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    folding: &'a SearchFolding,
+}
+
+impl FieldString {
+    fn new(field: Field, name: &str, searched: bool, x: &str, folding: &SearchFolding) -> Self {
+        let mut chars = Vec::with_capacity(x.len());
+        let mut origins = Vec::with_capacity(x.len());
+        for (i, c) in x.chars().enumerate() {
+            fold(c, folding, |c| {
+                chars.push(c);
+                origins.push(i);
+            });
+        }
+
+        let same = origins.iter().enumerate().all(|(i, &j)| i == j);
+        FieldString {
+            field,
+            name: name.to_lowercase().into(),
+            searched,
+            origins: (!same).then_some(origins),
+            chars,
+        }
+    }
+
+    fn origin(&self, i: usize) -> (Field, usize) {
+        (
+            self.field,
+            self.origins.as_ref().map_or(i, |origins| origins[i]),
+        )
+    }
+}
+
+pub fn track_string(
+    pos: usize,
+    track: &Track,
+    tags: &[(String, String)],
+    search_fields: &SearchFields,
+    folding: &SearchFolding,
+) -> TrackString {
+    let mut fields = Vec::with_capacity(4 + tags.len());
+
+    fields.push(FieldString::new(
+        Field::File,
+        "file",
+        search_fields.file,
+        &track.file,
+        folding,
+    ));
+
+    if let Some(title) = &track.title {
+        fields.push(FieldString::new(
+            Field::Title,
+            "title",
+            search_fields.title,
+            title,
+            folding,
+        ));
+    }
+
+    if let Some(artist) = &track.artist {
+        fields.push(FieldString::new(
+            Field::Artist,
+            "artist",
+            search_fields.artist,
+            artist,
+            folding,
+        ));
+    }
+
+    if let Some(album) = &track.album {
+        fields.push(FieldString::new(
+            Field::Album,
+            "album",
+            search_fields.album,
+            album,
+            folding,
+        ));
+    }
+
+    for (name, x) in tags {
+        fields.push(FieldString::new(Field::Other, name, false, x, folding));
+    }
+
+    TrackString { pos, fields }
+}
+
+// search the songs, returning the matching songs with the matched characters,
+// sorted by score if fuzzy
+pub fn search(queue_strings: &[TrackString], query: &Query, fuzzy: bool) -> Vec<(usize, Matched)> {
+    let mut results: Vec<_> = queue_strings
+        .iter()
+        .filter_map(|track| {
+            let (score, matched) = query.matches(track, fuzzy)?;
+            Some((score, track.pos, matched))
+        })
+        .collect();
+
+    if fuzzy {
+        // stable, so songs with the same score stay in queue order
+        results.sort_by_key(|(score, ..)| Reverse(*score));
+    }
+    results
+        .into_iter()
+        .map(|(_, i, matched)| (i, matched))
+        .collect()
+}
+
+// the first of the best matches
+fn best(xs: impl Iterator<Item = (i32, Matched)>) -> Option<(i32, Matched)> {
+    xs.reduce(|x, y| if y.0 > x.0 { y } else { x })
+}
+
+impl Query {
+    fn matches(&self, track: &TrackString, fuzzy: bool) -> Option<(i32, Matched)> {
+        match self {
+            Query::And(xs) => {
+                let mut score = 0;
+                let mut matched = Vec::new();
+                for x in xs {
+                    let (n, xs) = x.matches(track, fuzzy)?;
+                    score += n;
+                    matched.extend(xs);
+                }
+                Some((score, matched))
+            }
+            Query::Or(xs) => best(xs.iter().filter_map(|x| x.matches(track, fuzzy))),
+            Query::Not(x) => match x.matches(track, fuzzy) {
+                Some(_) => None,
+                None => Some((0, Vec::new())),
+            },
+            Query::Term(name, pattern) => best(
+                track
+                    .fields
+                    .iter()
+                    .filter(|field| {
+                        name.as_ref()
+                            .map_or(field.searched, |name| *field.name == **name)
+                    })
+                    .filter_map(|field| pattern.matches(field, fuzzy)),
+            ),
+        }
+    }
+
+    // an mpd filter expression that matches at least every song the query matches,
+    // None if the query can't be narrowed down, e.g. regular expressions and alternatives
+    pub fn filter(&self) -> Option<String> {
+        match self {
+            Query::And(xs) => {
+                let xs: Vec<_> = xs.iter().filter_map(Query::filter).collect();
+                match xs.len() {
+                    0 => None,
+                    1 => xs.into_iter().next(),
+                    _ => Some(format!("({})", xs.join(" AND "))),
+                }
+            }
+            Query::Or(_) | Query::Not(_) => None,
+            Query::Term(name, Pattern::Word(xs) | Pattern::Phrase(xs)) => {
+                let mut filter = format!("({} contains '", name.as_deref().unwrap_or("any"));
+                for &c in xs {
+                    if matches!(c, '\'' | '\\') {
+                        filter.push('\\');
+                    }
+                    filter.push(c);
+                }
+                filter.push_str("')");
+                Some(filter)
+            }
+            Query::Term(_, Pattern::Regex(_)) => None,
+        }
+    }
+}
+
+impl Pattern {
+    fn matches(&self, field: &FieldString, fuzzy: bool) -> Option<(i32, Matched)> {
+        let (score, positions) = match self {
+            Pattern::Word(xs) if fuzzy => fuzzy_match(&field.chars, xs)?,
+            Pattern::Word(xs) | Pattern::Phrase(xs) => {
+                let start = find(&field.chars, xs)?;
+                (xs.len() as i32 * 24, (start .. start + xs.len()).collect())
+            }
+            Pattern::Regex(re) => {
+                let text: String = field.chars.iter().collect();
+                let m = re.find(&text)?;
+                let start = text[.. m.start()].chars().count();
+                let len = m.as_str().chars().count();
+                (len as i32 * 16, (start .. start + len).collect())
+            }
+        };
+
+        Some((
+            score,
+            positions.into_iter().map(|i| field.origin(i)).collect(),
+        ))
+    }
+}
+
+fn find(xs: &[char], pat: &[char]) -> Option<usize> {
+    if pat.is_empty() {
+        Some(0)
+    } else {
+        xs.windows(pat.len()).position(|x| x == pat)
+    }
+}
+
+// find the shortest window containing the pattern in order starting from the first occurrence,
+// and score the characters in it similar to fzf
+fn fuzzy_match(xs: &[char], pat: &[char]) -> Option<(i32, Vec<usize>)> {
+    let mut chars = pat.iter().peekable();
+    let mut end = None;
+    for (i, c) in xs.iter().enumerate() {
+        if chars.next_if_eq(&c).is_some() && chars.peek().is_none() {
+            end = Some(i);
+            break;
+        }
+    }
+
+    let mut positions = Vec::with_capacity(pat.len());
+    let mut chars = pat.iter().rev().peekable();
+    for i in (0 ..= end?).rev() {
+        if chars.next_if_eq(&&xs[i]).is_some() {
+            positions.push(i);
+            if chars.peek().is_none() {
+                break;
+            }
+        }
+    }
+    positions.reverse();
+
+    let mut score = 0;
+    let mut prev: Option<usize> = None;
+    for &i in &positions {
+        score += 16;
+        // start of a word
+        if i == 0 || !xs[i - 1].is_alphanumeric() {
+            score += 8;
+        }
+        match prev {
+            Some(j) if j + 1 == i => score += 8,
+            Some(j) => score -= 2 + min(i - j, 16) as i32,
+            None => {}
+        }
+        prev = Some(i);
+    }
+
+    Some((score, positions))
+}
+
+// parse a query, e.g. `artist:"pink floyd" -live (title:/^the/ | album:wall)`
+pub fn parse(query: &str, folding: &SearchFolding) -> Result<Query, String> {
+    let mut parser = Parser {
+        chars: query.chars().peekable(),
+        folding,
+    };
+    let query = parser.or()?;
+    if parser.chars.next().is_some() {
+        Err(String::from("unmatched )"))
+    } else {
+        Ok(query)
+    }
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    // whether the current term ends here
+    fn at_end(&mut self) -> bool {
+        self.chars
+            .peek()
+            .is_none_or(|&c| c.is_whitespace() || c == '|' || c == ')')
+    }
+
+    fn or(&mut self) -> Result<Query, String> {
+        let mut xs = vec![self.and()?];
+        while self.eat('|') {
+            xs.push(self.and()?);
+        }
+
+        if xs.len() == 1 {
+            Ok(xs.remove(0))
+        } else if xs
+            .iter()
+            .any(|x| matches!(x, Query::And(xs) if xs.is_empty()))
+        {
+            Err(String::from("expected a term around |"))
+        } else {
+            Ok(Query::Or(xs))
+        }
+    }
+
+    fn and(&mut self) -> Result<Query, String> {
+        let mut xs = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.at_end() {
+                break;
+            }
+            xs.push(self.term()?);
+        }
+
+        Ok(if xs.len() == 1 {
+            xs.remove(0)
+        } else {
+            Query::And(xs)
+        })
+    }
+
+    fn term(&mut self) -> Result<Query, String> {
+        if self.eat('-') {
+            return if self.at_end() {
+                Err(String::from("expected a term after -"))
+            } else {
+                Ok(Query::Not(Box::new(self.term()?)))
+            };
+        }
+
+        if self.eat('(') {
+            let query = self.or()?;
+            return if self.eat(')') {
+                Ok(query)
+            } else {
+                Err(String::from("unclosed ("))
+            };
+        }
+
+        let mut prefix = String::new();
+        while let Some(c) = self.chars.next_if(|&c| c.is_alphanumeric() || c == '_') {
+            prefix.push(c);
+        }
+
+        if !prefix.is_empty() && self.eat(':') {
+            if self.at_end() {
+                Err(format!("expected a value after {prefix}:"))
+            } else {
+                Ok(Query::Term(
+                    Some(prefix.to_lowercase()),
+                    self.pattern(String::new())?,
+                ))
+            }
+        } else {
+            Ok(Query::Term(None, self.pattern(prefix)?))
+        }
+    }
+
+    fn pattern(&mut self, prefix: String) -> Result<Pattern, String> {
+        if prefix.is_empty() {
+            if self.eat('"') {
+                let phrase = self.quoted('"')?;
+                return Ok(Pattern::Phrase(self.fold(&phrase)));
+            }
+
+            if self.eat('/') {
+                return RegexBuilder::new(&self.quoted('/')?)
+                    .case_insensitive(true)
+                    .build()
+                    .map(Pattern::Regex)
+                    .map_err(|e| {
+                        // the last line of a syntax error describes the error
+                        let e = e.to_string();
+                        let e = e.lines().last().unwrap_or_default();
+                        format!("invalid regex: {}", e.trim_start_matches("error: "))
+                    });
+            }
+        }
+
+        let mut word = prefix;
+        while let Some(c) = self
+            .chars
+            .next_if(|&c| !c.is_whitespace() && !matches!(c, '|' | '(' | ')' | '"'))
+        {
+            word.push(c);
+        }
+        Ok(Pattern::Word(self.fold(&word)))
+    }
+
+    // read until the closing delimiter, which can be escaped with a backslash
+    fn quoted(&mut self, delim: char) -> Result<String, String> {
+        let mut xs = String::new();
+        let mut escaped = false;
+        for c in self.chars.by_ref() {
+            if escaped {
+                if c != delim {
+                    xs.push('\\');
+                }
+                xs.push(c);
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delim {
+                return Ok(xs);
+            } else {
+                xs.push(c);
+            }
+        }
+
+        Err(format!("unclosed {delim}"))
+    }
+
+    fn fold(&self, x: &str) -> Vec<char> {
+        let mut xs = Vec::with_capacity(x.len());
+        for c in x.chars() {
+            fold(c, self.folding, |c| xs.push(c));
+        }
+        xs
+    }
+}
+
+// normalize a character for matching, calling f on each resulting character
+fn fold(c: char, folding: &SearchFolding, mut f: impl FnMut(char)) {
+    let mut push = |c: char| {
+        // keep the voiced sound marks so kana like が and か stay distinct
+        if folding.accents && is_combining_mark(c) && !matches!(c, '\u{3099}' | '\u{309a}') {
+            return;
+        }
+
+        // fold katakana to hiragana
+        let c = if folding.kana && matches!(c, '\u{30a1}' ..= '\u{30f6}') {
+            char::from_u32(c as u32 - 0x60).unwrap_or(c)
+        } else {
+            c
+        };
+
+        c.to_lowercase().for_each(&mut f);
+    };
+
+    if folding.width {
+        decompose_compatible(c, push);
+    } else if folding.accents {
+        decompose_canonical(c, push);
+    } else {
+        push(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: SearchFields = SearchFields {
+        file: false,
+        title: true,
+        artist: true,
+        album: true,
+    };
+
+    const FOLDING: SearchFolding = SearchFolding {
+        accents: true,
+        width: true,
+        kana: false,
+    };
+
+    fn song(pos: usize, title: &str, artist: &str) -> Track {
+        Track {
+            id: pos,
+            file: format!("{pos}.flac"),
+            artist: Some(artist.into()),
+            album: None,
+            title: Some(title.into()),
+            time: 0,
+        }
+    }
+
+    fn track(pos: usize, title: &str, artist: &str, tags: &[(&str, &str)]) -> TrackString {
+        let tags: Vec<_> = tags
+            .iter()
+            .map(|&(name, x)| (name.into(), x.into()))
+            .collect();
+        track_string(pos, &song(pos, title, artist), &tags, &FIELDS, &FOLDING)
+    }
+
+    fn results(query: &str, tracks: &[TrackString], fuzzy: bool) -> Vec<usize> {
+        let Ok(query) = parse(query, &FOLDING) else {
+            panic!("failed to parse {query}");
+        };
+        search(tracks, &query, fuzzy)
+            .into_iter()
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn error(query: &str) -> String {
+        match parse(query, &FOLDING) {
+            Ok(_) => panic!("parsed {query}"),
+            Err(e) => e,
+        }
+    }
+
+    // the matched characters of the title, with both the title and the query folded
+    fn folded(title: &str, query: &str, folding: &SearchFolding) -> Option<Matched> {
+        let track = track_string(0, &song(0, title, ""), &[], &FIELDS, folding);
+        let (_, matched) = parse(query, folding).ok()?.matches(&track, false)?;
+        Some(matched)
+    }
+
+    fn filter(query: &str) -> Option<String> {
+        parse(query, &FOLDING).ok()?.filter()
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(error("a |"), "expected a term around |");
+        assert_eq!(error("| a"), "expected a term around |");
+        assert_eq!(error("a)"), "unmatched )");
+        assert_eq!(error("(a"), "unclosed (");
+        assert_eq!(error("a -"), "expected a term after -");
+        assert_eq!(error("artist:"), "expected a value after artist:");
+        assert_eq!(error(r#""a"#), r#"unclosed ""#);
+        assert_eq!(error("/a"), "unclosed /");
+        assert!(error("/(/").starts_with("invalid regex: "));
+    }
+
+    #[test]
+    fn precedence() {
+        let tracks = [
+            track(0, "Time", "Pink Floyd", &[]),
+            track(1, "Creep", "Radiohead", &[]),
+            track(2, "Sympathique", "Pink Martini", &[]),
+        ];
+        assert_eq!(results("", &tracks, false), [0, 1, 2]);
+        assert_eq!(results("pink floyd | radiohead", &tracks, false), [0, 1]);
+        assert_eq!(results("pink (floyd | martini)", &tracks, false), [0, 2]);
+        assert_eq!(results("pink -floyd", &tracks, false), [2]);
+        assert_eq!(results("-(pink | creep)", &tracks, false), []);
+    }
+
+    #[test]
+    fn quoting() {
+        let tracks = [
+            track(0, "Time", "Pink Floyd", &[("Genre", "Rock")]),
+            track(1, "Floyd", "Pink", &[]),
+            track(2, r#"say "hi""#, "(a|b)", &[]),
+        ];
+        assert_eq!(results("pink floyd", &tracks, false), [0, 1]);
+        assert_eq!(results(r#""pink floyd""#, &tracks, false), [0]);
+        assert_eq!(results(r#"artist:"pink floyd""#, &tracks, false), [0]);
+        assert_eq!(results("title:floyd", &tracks, false), [1]);
+        assert_eq!(results(r#""say \"hi\"""#, &tracks, false), [2]);
+        assert_eq!(results(r#""(a|b)""#, &tracks, false), [2]);
+
+        // tags are only searched with a field filter
+        assert_eq!(results("rock", &tracks, false), []);
+        assert_eq!(results("GENRE:rock", &tracks, false), [0]);
+    }
+
+    #[test]
+    fn regex() {
+        let tracks = [
+            track(0, "The Wall", "Pink Floyd", &[]),
+            track(1, "Another Brick in the Wall", "Pink Floyd", &[]),
+            track(2, "Track 12", "a/b", &[]),
+        ];
+        assert_eq!(results("title:/^the/", &tracks, false), [0]);
+        assert_eq!(results("/wall$/", &tracks, false), [0, 1]);
+        assert_eq!(results(r"/\d+/", &tracks, false), [2]);
+        assert_eq!(results(r"artist:/a\/b/", &tracks, false), [2]);
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            filter(r#"artist:"it's" live"#).as_deref(),
+            Some(r"((artist contains 'it\'s') AND (any contains 'live'))"),
+        );
+        assert_eq!(
+            filter(r"a\b /x/").as_deref(),
+            Some(r"(any contains 'a\\b')"),
+        );
+        assert_eq!(filter("a | b"), None);
+        assert_eq!(filter("-a"), None);
+        assert_eq!(filter("/x/"), None);
+    }
+
+    #[test]
+    fn fuzzy_order() {
+        let tracks = [
+            track(0, "axbxc", "", &[]),
+            track(1, "acb", "", &[]),
+            track(2, "xabcx", "", &[]),
+            track(3, "abc", "", &[]),
+            track(4, "abc", "", &[]),
+        ];
+        // consecutive characters at the start of a word first, ties in queue order
+        assert_eq!(results("abc", &tracks, true), [3, 4, 2, 0]);
+        assert_eq!(results("abc", &tracks, false), [2, 3, 4]);
+    }
+
+    #[test]
+    fn folding() {
+        assert!(folded("Beyoncé", "beyonce", &FOLDING).is_some());
+        assert!(folded("Beyonce", "BEYONCÉ", &FOLDING).is_some());
+        assert!(folded("Björk", "bjork", &FOLDING).is_some());
+        assert!(folded("Straße", "STRAẞE", &FOLDING).is_some());
+        assert!(folded("ＹＭＯ", "ymo", &FOLDING).is_some());
+        assert!(folded("Björk", "bjo\u{308}rk", &FOLDING).is_some());
+
+        let none = SearchFolding {
+            accents: false,
+            width: false,
+            kana: false,
+        };
+        assert!(folded("Beyoncé", "beyonce", &none).is_none());
+        assert!(folded("Beyoncé", "BEYONCÉ", &none).is_some());
+        assert!(folded("Straße", "STRAẞE", &none).is_some());
+        assert!(folded("ＹＭＯ", "ymo", &none).is_none());
+
+        let kana = SearchFolding {
+            accents: true,
+            width: true,
+            kana: true,
+        };
+        assert!(folded("カタカナ", "かたかな", &kana).is_some());
+        assert!(folded("カタカナ", "かたかな", &FOLDING).is_none());
+        assert!(folded("か", "ガ", &kana).is_none());
+    }
+
+    #[test]
+    fn folded_origins() {
+        // the combining accent is dropped, and the ligature is split
+        assert_eq!(
+            folded("e\u{301}ﬁx", "x", &FOLDING),
+            Some(vec![(Field::Title, 3)]),
+        );
+        assert_eq!(
+            folded("e\u{301}ﬁx", "efi", &FOLDING),
+            Some(vec![
+                (Field::Title, 0),
+                (Field::Title, 2),
+                (Field::Title, 2)
+            ]),
+        );
+    }
+}
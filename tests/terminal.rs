@@ -0,0 +1,187 @@
+#![cfg(unix)]
+
+use libc::{c_int, termios, winsize, ECHO, ICANON, SIGHUP, SIGINT, SIGTERM};
+
+use std::{
+    env,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    mem,
+    net::TcpListener,
+    os::unix::io::{AsRawFd, FromRawFd},
+    process::{Command, Stdio},
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const ENTER_ALTERNATE_SCREEN: &[u8] = b"\x1b[?1049h";
+const LEAVE_ALTERNATE_SCREEN: &[u8] = b"\x1b[?1049l";
+const DISABLE_MOUSE_CAPTURE: &[u8] = b"\x1b[?1000l";
+const DISABLE_BRACKETED_PASTE: &[u8] = b"\x1b[?2004l";
+
+// an mpd server with an empty queue that never reports any changes, and never responds to `repeat`
+fn serve() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                stream.write_all(b"OK MPD 0.23.5\n").unwrap();
+                for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    let res: &[u8] = if line == "status" {
+                        b"repeat: 0\nrandom: 0\nsingle: 0\nconsume: 0\nplaylistlength: 0\nOK\n"
+                    } else if line.starts_with("idle") || line.starts_with("repeat") {
+                        continue;
+                    } else {
+                        b"OK\n"
+                    };
+                    if stream.write_all(res).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    port
+}
+
+fn openpty() -> (File, File) {
+    let mut master = 0;
+    let mut slave = 0;
+    let size = winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    assert_eq!(
+        unsafe { libc::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), &size) },
+        0,
+    );
+    unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) }
+}
+
+fn termios(tty: &File) -> termios {
+    unsafe {
+        let mut termios = mem::zeroed();
+        assert_eq!(libc::tcgetattr(tty.as_raw_fd(), &mut termios), 0);
+        termios
+    }
+}
+
+fn wait_until(timeout: Duration, mut f: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if f() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+fn contains(xs: &[u8], pat: &[u8]) -> bool {
+    xs.windows(pat.len()).any(|x| x == pat)
+}
+
+fn restores_terminal_on(sig: c_int, keys: &[u8]) {
+    let port = serve();
+    let (master, slave) = openpty();
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let home = env::temp_dir().join(format!(
+        "mmtc-test-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed),
+    ));
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mmtc"))
+        .args(["--address", &format!("127.0.0.1:{port}")])
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("XDG_STATE_HOME")
+        .env_remove("XDG_DATA_HOME")
+        .stdin(Stdio::from(slave.try_clone().unwrap()))
+        .stdout(Stdio::from(slave.try_clone().unwrap()))
+        .stderr(Stdio::from(slave.try_clone().unwrap()))
+        .spawn()
+        .unwrap();
+
+    // keep reading so mmtc never blocks on a full terminal
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let output1 = Arc::clone(&output);
+    let mut master1 = master.try_clone().unwrap();
+    thread::spawn(move || {
+        let buf = &mut [0; 4096];
+        while let Ok(n @ 1 ..) = master1.read(buf) {
+            output1.lock().unwrap().extend_from_slice(&buf[.. n]);
+        }
+    });
+
+    assert!(
+        wait_until(Duration::from_secs(10), || contains(
+            &output.lock().unwrap(),
+            ENTER_ALTERNATE_SCREEN,
+        )),
+        "mmtc did not start: {}",
+        String::from_utf8_lossy(&output.lock().unwrap()),
+    );
+    assert_eq!(termios(&slave).c_lflag & (ICANON | ECHO), 0);
+
+    (&master).write_all(keys).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(unsafe { libc::kill(child.id() as i32, sig) }, 0);
+    let mut status = None;
+    assert!(wait_until(Duration::from_secs(10), || {
+        status = child.try_wait().unwrap();
+        status.is_some()
+    }));
+    assert_eq!(status.unwrap().code(), Some(128 + sig));
+
+    // the output may still be read after mmtc exited
+    assert!(wait_until(Duration::from_secs(1), || {
+        let output = output.lock().unwrap();
+        let start = output
+            .windows(ENTER_ALTERNATE_SCREEN.len())
+            .position(|x| x == ENTER_ALTERNATE_SCREEN)
+            .unwrap();
+        [
+            LEAVE_ALTERNATE_SCREEN,
+            DISABLE_MOUSE_CAPTURE,
+            DISABLE_BRACKETED_PASTE,
+        ]
+        .into_iter()
+        .all(|pat| contains(&output[start ..], pat))
+    }));
+    assert_eq!(termios(&slave).c_lflag & (ICANON | ECHO), ICANON | ECHO);
+}
+
+#[test]
+fn restores_terminal_on_sigterm() {
+    restores_terminal_on(SIGTERM, b"");
+}
+
+#[test]
+fn restores_terminal_on_sighup() {
+    restores_terminal_on(SIGHUP, b"");
+}
+
+#[test]
+fn restores_terminal_on_sigint() {
+    restores_terminal_on(SIGINT, b"");
+}
+
+#[test]
+fn restores_terminal_while_waiting_for_mpd() {
+    restores_terminal_on(SIGTERM, b"r");
+}